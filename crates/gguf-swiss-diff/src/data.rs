@@ -0,0 +1,230 @@
+use anyhow::{bail, Error};
+use gguf_swiss::{Header, TensorInfo};
+
+use crate::{
+    diff::{ChangeKind, DataDiff, Diff, TensorChange, TensorSummary},
+    Model,
+};
+
+/// Compare the data of tensors present in both models.
+///
+/// Tensors with differing data are added to the diff as changed, tensors with identical data are
+/// left out.
+/// Tensors that can't be compared are reported on stderr and skipped.
+pub fn compare<'a>(
+    diff: &mut Diff<'a>,
+    (old_header, old): (&'a Header, &mut Model),
+    (new_header, new): (&'a Header, &mut Model),
+) {
+    for old_tensor in &old_header.tensors {
+        let Some(new_tensor) = new_header.find_tensor(&old_tensor.name) else {
+            continue;
+        };
+
+        let data = match compare_tensor((old_tensor, old), (new_tensor, new), CHUNK_VALUES) {
+            Ok(data) => data,
+            Err(error) => {
+                eprintln!("skipping data of {:?}: {:#}", old_tensor.name, error);
+                continue;
+            }
+        };
+
+        // Attach to an existing type or shape change, or add a new change if the data differs
+        if let Some(change) = diff.tensors.iter_mut().find(|c| c.name == old_tensor.name) {
+            change.data = Some(data);
+        } else if !data.identical {
+            diff.tensors.push(TensorChange {
+                name: &old_tensor.name,
+                kind: ChangeKind::Changed,
+                old: Some(TensorSummary::new(old_tensor)),
+                new: Some(TensorSummary::new(new_tensor)),
+                data: Some(data),
+            });
+        }
+    }
+}
+
+/// Amount of values to compare at once, a multiple of every block size.
+const CHUNK_VALUES: u64 = 1024 * 1024;
+
+fn compare_tensor(
+    (old_tensor, old): (&TensorInfo, &mut Model),
+    (new_tensor, new): (&TensorInfo, &mut Model),
+    chunk_values: u64,
+) -> Result<DataDiff, Error> {
+    if old_tensor.dimensions.total() != new_tensor.dimensions.total() {
+        bail!("element count changed");
+    }
+
+    // Read the old tensor in chunks, and the same range of values from the new tensor
+    let old_type = old_tensor.tensor_type;
+    let chunk_size = chunk_values / old_type.block_size() * old_type.type_size();
+    let mut accumulator = Accumulator::default();
+    let mut start = 0;
+
+    old.read_chunks(old_tensor, chunk_size as usize, |old_values| {
+        let end = start + old_values.len() as u64;
+        let new_values = new.read_values(new_tensor, start..end)?;
+        accumulator.add(old_values, &new_values);
+        start = end;
+        Ok(())
+    })?;
+
+    Ok(accumulator.finish())
+}
+
+/// Numerical difference between two sets of values, calculated incrementally over chunks.
+///
+/// Values are compared by their bits, so identical NaN values don't differ.
+/// Values that only differ in being NaN result in a NaN max abs diff.
+#[derive(Debug)]
+struct Accumulator {
+    identical: bool,
+    max_abs_diff: f64,
+    dot: f64,
+    old_norm: f64,
+    new_norm: f64,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self {
+            identical: true,
+            max_abs_diff: 0.0,
+            dot: 0.0,
+            old_norm: 0.0,
+            new_norm: 0.0,
+        }
+    }
+}
+
+impl Accumulator {
+    fn add(&mut self, old: &[f32], new: &[f32]) {
+        for (a, b) in old.iter().zip(new) {
+            let same = a.to_bits() == b.to_bits();
+            self.identical &= same;
+            let (a, b) = (*a as f64, *b as f64);
+
+            // Identical values, including infinities and NaN, don't differ
+            let abs_diff = if same || a == b { 0.0 } else { (a - b).abs() };
+            self.max_abs_diff = if abs_diff.is_nan() || self.max_abs_diff.is_nan() {
+                f64::NAN
+            } else {
+                self.max_abs_diff.max(abs_diff)
+            };
+
+            self.dot += a * b;
+            self.old_norm += a * a;
+            self.new_norm += b * b;
+        }
+    }
+
+    fn finish(self) -> DataDiff {
+        DataDiff {
+            max_abs_diff: self.max_abs_diff,
+            cosine_similarity: self.dot / (self.old_norm.sqrt() * self.new_norm.sqrt()),
+            identical: self.identical,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Write, path::Path};
+
+    use gguf_swiss::{Header, TensorDimensions, TensorInfo, TensorType};
+
+    use crate::{
+        data::{compare_tensor, Accumulator},
+        diff::DataDiff,
+        Model,
+    };
+
+    fn calculate(old: &[f32], new: &[f32]) -> DataDiff {
+        // Split over chunks, like tensor data is read
+        let mut accumulator = Accumulator::default();
+        let half = old.len() / 2;
+        accumulator.add(&old[..half], &new[..half]);
+        accumulator.add(&old[half..], &new[half..]);
+        accumulator.finish()
+    }
+
+    #[test]
+    fn calculate_data_diff() {
+        let diff = calculate(&[1.0, 0.0, 2.0], &[1.0, 0.5, 2.0]);
+        assert_eq!(diff.max_abs_diff, 0.5);
+        assert!((diff.cosine_similarity - 5.0 / (5f64.sqrt() * 5.25f64.sqrt())).abs() < 1e-12);
+
+        assert!(!diff.identical);
+
+        let diff = calculate(&[1.0, f32::INFINITY], &[1.0, f32::INFINITY]);
+        assert_eq!(diff.max_abs_diff, 0.0);
+        assert!(diff.identical);
+
+        let diff = calculate(&[f32::NAN, 1.0], &[f32::NAN, 1.0]);
+        assert_eq!(diff.max_abs_diff, 0.0);
+        assert!(diff.identical);
+
+        let diff = calculate(&[f32::NAN], &[1.0]);
+        assert!(diff.max_abs_diff.is_nan());
+        assert!(!diff.identical);
+    }
+
+    fn write_model(path: &Path, tensor_type: TensorType, data: &[u8]) -> TensorInfo {
+        let tensor = TensorInfo {
+            name: "output.weight".to_string(),
+            tensor_type,
+            dimensions: TensorDimensions([64, 8, 0, 0]),
+            offset: 0,
+        };
+        let header = Header {
+            metadata: Vec::new(),
+            tensors: vec![tensor.clone()],
+        };
+
+        let mut file = File::create(path).unwrap();
+        gguf_swiss::write_header(&mut file, &header).unwrap();
+        gguf_swiss::write_padding(&mut file).unwrap();
+        file.write_all(data).unwrap();
+
+        tensor
+    }
+
+    #[test]
+    fn compare_chunks_across_types() {
+        let values: Vec<_> = (0..512).map(|i| (i % 50) as f32 - 25.0).collect();
+        let f32_data: Vec<_> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+
+        // Q8_0 blocks with a scale of 1.0, so the quantized values are exact
+        let mut q8_data = Vec::new();
+        for block in values.chunks(32) {
+            q8_data.extend([0x00, 0x3c]);
+            q8_data.extend(block.iter().map(|v| *v as i8 as u8));
+        }
+
+        let temp = std::env::temp_dir();
+        let old_path = temp.join(format!("gguf-swiss-diff-{}-old", std::process::id()));
+        let new_path = temp.join(format!("gguf-swiss-diff-{}-new", std::process::id()));
+        let old_tensor = write_model(&old_path, TensorType::F32, &f32_data);
+        let new_tensor = write_model(&new_path, TensorType::Q8_0, &q8_data);
+        let (mut old, _) = Model::open(old_path.to_str().unwrap()).unwrap();
+        let (mut new, _) = Model::open(new_path.to_str().unwrap()).unwrap();
+
+        // Chunks of 96 values don't divide the tensor, so the last chunk is partial
+        let diff = compare_tensor((&old_tensor, &mut old), (&new_tensor, &mut new), 96).unwrap();
+        assert!(diff.identical);
+        assert_eq!(diff.max_abs_diff, 0.0);
+
+        // Change a value in the last, partial chunk
+        q8_data[(500 / 32) * 34 + 2 + 500 % 32] = 100;
+        write_model(&new_path, TensorType::Q8_0, &q8_data);
+        let (mut new, _) = Model::open(new_path.to_str().unwrap()).unwrap();
+
+        let diff = compare_tensor((&old_tensor, &mut old), (&new_tensor, &mut new), 96).unwrap();
+        assert!(!diff.identical);
+        assert_eq!(diff.max_abs_diff, (100.0 - values[500]) as f64);
+
+        std::fs::remove_file(old_path).unwrap();
+        std::fs::remove_file(new_path).unwrap();
+    }
+}
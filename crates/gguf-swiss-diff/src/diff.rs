@@ -0,0 +1,201 @@
+use gguf_swiss::{Header, MetadataValue, TensorDimensions, TensorInfo, TensorType};
+use serde::Serialize;
+
+/// Differences between two model headers, and optionally their tensor data.
+#[derive(Serialize, Debug, Default)]
+pub struct Diff<'a> {
+    pub metadata: Vec<MetadataChange<'a>>,
+    pub tensors: Vec<TensorChange<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MetadataChange<'a> {
+    pub key: &'a str,
+    pub kind: ChangeKind,
+
+    /// Set if the value's type changed, which includes changes to an array's element type.
+    pub type_changed: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<&'a MetadataValue>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<&'a MetadataValue>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TensorChange<'a> {
+    pub name: &'a str,
+    pub kind: ChangeKind,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<TensorSummary>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<TensorSummary>,
+
+    /// Numerical differences of the tensor data, if compared.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<DataDiff>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct TensorSummary {
+    pub tensor_type: TensorType,
+    pub dimensions: TensorDimensions,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct DataDiff {
+    pub max_abs_diff: f64,
+    pub cosine_similarity: f64,
+
+    /// Set if all values are bitwise identical.
+    #[serde(skip)]
+    pub identical: bool,
+}
+
+impl<'a> Diff<'a> {
+    /// Compare the metadata and tensor information of two headers.
+    pub fn new(old: &'a Header, new: &'a Header) -> Self {
+        let mut diff = Self::default();
+
+        for (key, old_value) in &old.metadata {
+            let change = match new.find_metadata(key) {
+                None => MetadataChange {
+                    key,
+                    kind: ChangeKind::Removed,
+                    type_changed: false,
+                    old: Some(old_value),
+                    new: None,
+                },
+                Some(new_value) if new_value != old_value => MetadataChange {
+                    key,
+                    kind: ChangeKind::Changed,
+                    type_changed: old_value.type_name() != new_value.type_name(),
+                    old: Some(old_value),
+                    new: Some(new_value),
+                },
+                Some(_) => continue,
+            };
+            diff.metadata.push(change);
+        }
+        for (key, new_value) in &new.metadata {
+            if old.find_metadata(key).is_none() {
+                diff.metadata.push(MetadataChange {
+                    key,
+                    kind: ChangeKind::Added,
+                    type_changed: false,
+                    old: None,
+                    new: Some(new_value),
+                });
+            }
+        }
+
+        for old_tensor in &old.tensors {
+            let change = match new.find_tensor(&old_tensor.name) {
+                None => TensorChange {
+                    name: &old_tensor.name,
+                    kind: ChangeKind::Removed,
+                    old: Some(TensorSummary::new(old_tensor)),
+                    new: None,
+                    data: None,
+                },
+                Some(new_tensor)
+                    if new_tensor.tensor_type != old_tensor.tensor_type
+                        || new_tensor.dimensions != old_tensor.dimensions =>
+                {
+                    TensorChange {
+                        name: &old_tensor.name,
+                        kind: ChangeKind::Changed,
+                        old: Some(TensorSummary::new(old_tensor)),
+                        new: Some(TensorSummary::new(new_tensor)),
+                        data: None,
+                    }
+                }
+                Some(_) => continue,
+            };
+            diff.tensors.push(change);
+        }
+        for new_tensor in &new.tensors {
+            if old.find_tensor(&new_tensor.name).is_none() {
+                diff.tensors.push(TensorChange {
+                    name: &new_tensor.name,
+                    kind: ChangeKind::Added,
+                    old: None,
+                    new: Some(TensorSummary::new(new_tensor)),
+                    data: None,
+                });
+            }
+        }
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metadata.is_empty() && self.tensors.is_empty()
+    }
+}
+
+impl TensorSummary {
+    pub fn new(tensor: &TensorInfo) -> Self {
+        Self {
+            tensor_type: tensor.tensor_type,
+            dimensions: tensor.dimensions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gguf_swiss::{Header, MetadataValue};
+
+    use crate::diff::{ChangeKind, Diff};
+
+    #[test]
+    fn metadata_changes() {
+        let old = Header {
+            metadata: vec![
+                ("same".to_string(), MetadataValue::UInt32(1)),
+                ("nan".to_string(), MetadataValue::Float32(f32::NAN)),
+                ("removed".to_string(), MetadataValue::UInt32(2)),
+                ("value".to_string(), MetadataValue::UInt32(3)),
+                ("type".to_string(), MetadataValue::UInt32(4)),
+            ],
+            tensors: Vec::new(),
+        };
+        let new = Header {
+            metadata: vec![
+                ("same".to_string(), MetadataValue::UInt32(1)),
+                ("nan".to_string(), MetadataValue::Float32(f32::NAN)),
+                ("value".to_string(), MetadataValue::UInt32(5)),
+                ("type".to_string(), MetadataValue::UInt64(4)),
+                ("added".to_string(), MetadataValue::Bool(true)),
+            ],
+            tensors: Vec::new(),
+        };
+
+        let diff = Diff::new(&old, &new);
+        let changes: Vec<_> = diff
+            .metadata
+            .iter()
+            .map(|c| (c.key, c.kind, c.type_changed))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("removed", ChangeKind::Removed, false),
+                ("value", ChangeKind::Changed, false),
+                ("type", ChangeKind::Changed, true),
+                ("added", ChangeKind::Added, false),
+            ]
+        );
+    }
+}
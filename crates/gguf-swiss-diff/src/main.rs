@@ -0,0 +1,148 @@
+mod data;
+mod diff;
+mod text;
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    ops::Range,
+    process::ExitCode,
+};
+
+use anyhow::{bail, Context, Error};
+use clap::{Parser, ValueEnum};
+use gguf_swiss::{align_offset, Header, TensorInfo};
+
+use crate::diff::Diff;
+
+fn main() -> ExitCode {
+    // Like diff, exit with 2 on errors, as 1 means the models differ
+    match run() {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("Error: {:?}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run() -> Result<ExitCode, Error> {
+    let args = Args::parse();
+
+    let (mut old, old_header) = Model::open(&args.old).context("failed to read old model")?;
+    let (mut new, new_header) = Model::open(&args.new).context("failed to read new model")?;
+
+    let mut diff = Diff::new(&old_header, &new_header);
+    if args.data {
+        data::compare(&mut diff, (&old_header, &mut old), (&new_header, &mut new));
+    }
+
+    match args.format {
+        Format::Text => text::print(&diff),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+    }
+
+    // Like diff, exit with 1 if the models differ
+    let code = if diff.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    };
+    Ok(code)
+}
+
+/// GGUF Swiss Army Knife, model comparison utility.
+///
+/// Exits with status 1 if the models differ, and 2 on errors.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Path to the old model.
+    old: String,
+
+    /// Path to the new model.
+    new: String,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Read and compare the tensor data of tensors present in both models.
+    ///
+    /// Quantized tensors are dequantized first, so tensors can be compared across types.
+    #[arg(long)]
+    data: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Format {
+    Text,
+    Json,
+}
+
+/// An opened model, to read tensor data from.
+pub struct Model {
+    file: File,
+    data_start: u64,
+}
+
+impl Model {
+    fn open(path: &str) -> Result<(Self, Header), Error> {
+        let mut file = File::open(path)?;
+        let header = gguf_swiss::read_header(&mut file)?;
+        let data_start = align_offset(file.stream_position()?);
+
+        Ok((Self { file, data_start }, header))
+    }
+
+    /// Read a tensor's data in chunks of at most `chunk_size` bytes, dequantized to f32.
+    pub fn read_chunks<F>(
+        &mut self,
+        tensor: &TensorInfo,
+        chunk_size: usize,
+        mut f: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&[f32]) -> Result<(), Error>,
+    {
+        gguf_swiss::read_tensor_chunks(
+            &mut self.file,
+            self.data_start,
+            tensor,
+            chunk_size,
+            |data| {
+                let values = gguf_swiss::dequantize(tensor.tensor_type, data)?;
+                f(&values)
+            },
+        )
+    }
+
+    /// Read a range of a tensor's values, dequantized to f32.
+    ///
+    /// The range must start and end on block boundaries.
+    pub fn read_values(
+        &mut self,
+        tensor: &TensorInfo,
+        range: Range<u64>,
+    ) -> Result<Vec<f32>, Error> {
+        let block_size = tensor.tensor_type.block_size();
+        let type_size = tensor.tensor_type.type_size();
+        if !range.start.is_multiple_of(block_size) || !range.end.is_multiple_of(block_size) {
+            bail!(
+                "range {:?} doesn't align with blocks of {}",
+                range,
+                block_size
+            );
+        }
+
+        let start = self.data_start + tensor.offset + range.start / block_size * type_size;
+        self.file.seek(SeekFrom::Start(start))?;
+
+        let mut data = vec![0u8; ((range.end - range.start) / block_size * type_size) as usize];
+        self.file
+            .read_exact(&mut data)
+            .with_context(|| format!("failed to read tensor {:?}", tensor.name))?;
+
+        gguf_swiss::dequantize(tensor.tensor_type, &data)
+    }
+}
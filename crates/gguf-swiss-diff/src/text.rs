@@ -0,0 +1,185 @@
+use gguf_swiss::{format_bytes, MetadataArray, MetadataValue};
+
+use crate::diff::{ChangeKind, Diff, TensorSummary};
+
+/// Print the diff for humans, with lines prefixed by `+` for added, `-` for removed, and `~` for
+/// changed entries.
+pub fn print(diff: &Diff) {
+    if diff.is_empty() {
+        println!("models are identical");
+        return;
+    }
+
+    if !diff.metadata.is_empty() {
+        println!("Metadata:");
+    }
+    for change in &diff.metadata {
+        match (change.old, change.new) {
+            (None, Some(new)) => println!("+ {}: {}", change.key, new),
+            (Some(old), None) => println!("- {}: {}", change.key, old),
+            (Some(old), Some(new)) if change.type_changed => println!(
+                "~ {}: type changed, {} {} -> {} {}",
+                change.key,
+                old.type_name(),
+                old,
+                new.type_name(),
+                new
+            ),
+            (Some(old), Some(new)) => match (old, new) {
+                (MetadataValue::Array(old), MetadataValue::Array(new)) => println!(
+                    "~ {}: {} -> {}, {}",
+                    change.key,
+                    format_array(old),
+                    format_array(new),
+                    format_array_difference(old, new)
+                ),
+                _ => println!("~ {}: {} -> {}", change.key, old, new),
+            },
+            (None, None) => {}
+        }
+    }
+
+    if !diff.tensors.is_empty() {
+        println!("Tensors:");
+    }
+    for change in &diff.tensors {
+        match (change.kind, change.old, change.new) {
+            (ChangeKind::Added, _, Some(new)) => {
+                println!("+ {}: {}", change.name, format_tensor(new))
+            }
+            (ChangeKind::Removed, Some(old), _) => {
+                println!("- {}: {}", change.name, format_tensor(old))
+            }
+            (_, Some(old), Some(new)) => {
+                let mut parts = Vec::new();
+                if old.tensor_type != new.tensor_type {
+                    parts.push(format!(
+                        "type {:?} -> {:?}",
+                        old.tensor_type, new.tensor_type
+                    ));
+                }
+                if old.dimensions != new.dimensions {
+                    parts.push(format!(
+                        "dimensions {} -> {}",
+                        old.dimensions, new.dimensions
+                    ));
+                }
+                if let Some(data) = &change.data {
+                    parts.push(format!(
+                        "max abs diff {}, cosine similarity {:.6}",
+                        data.max_abs_diff, data.cosine_similarity
+                    ));
+                }
+                println!("~ {}: {}", change.name, parts.join(", "));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn format_tensor(tensor: TensorSummary) -> String {
+    format!("{:?} {}", tensor.tensor_type, tensor.dimensions)
+}
+
+fn format_array(array: &MetadataArray) -> String {
+    format!("[{:?}; {}]", array.ty(), array.len())
+}
+
+/// Describe the first element that differs between two arrays of the same type.
+fn format_array_difference(old: &MetadataArray, new: &MetadataArray) -> String {
+    let index = first_difference(old, new);
+    format!(
+        "first difference at index {}: {} -> {}",
+        index,
+        format_element(old, index),
+        format_element(new, index)
+    )
+}
+
+fn first_difference(old: &MetadataArray, new: &MetadataArray) -> usize {
+    fn position<T>(a: &[T], b: &[T], eq: impl Fn(&T, &T) -> bool) -> usize {
+        let common = a.len().min(b.len());
+        a.iter()
+            .zip(b)
+            .position(|(a, b)| !eq(a, b))
+            .unwrap_or(common)
+    }
+
+    match (old, new) {
+        (MetadataArray::UInt8(a), MetadataArray::UInt8(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::Int8(a), MetadataArray::Int8(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::UInt16(a), MetadataArray::UInt16(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::Int16(a), MetadataArray::Int16(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::UInt32(a), MetadataArray::UInt32(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::Int32(a), MetadataArray::Int32(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::Float32(a), MetadataArray::Float32(b)) => {
+            position(a, b, |a, b| a.to_bits() == b.to_bits())
+        }
+        (MetadataArray::Bool(a), MetadataArray::Bool(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::String(a), MetadataArray::String(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::Array(a), MetadataArray::Array(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::UInt64(a), MetadataArray::UInt64(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::Int64(a), MetadataArray::Int64(b)) => position(a, b, PartialEq::eq),
+        (MetadataArray::Float64(a), MetadataArray::Float64(b)) => {
+            position(a, b, |a, b| a.to_bits() == b.to_bits())
+        }
+        _ => 0,
+    }
+}
+
+/// Format a single element of an array, or `none` if the array is too short.
+fn format_element(array: &MetadataArray, index: usize) -> String {
+    if index >= array.len() {
+        return "none".to_string();
+    }
+
+    match array {
+        MetadataArray::UInt8(v) => v[index].to_string(),
+        MetadataArray::Int8(v) => v[index].to_string(),
+        MetadataArray::UInt16(v) => v[index].to_string(),
+        MetadataArray::Int16(v) => v[index].to_string(),
+        MetadataArray::UInt32(v) => v[index].to_string(),
+        MetadataArray::Int32(v) => v[index].to_string(),
+        MetadataArray::Float32(v) => v[index].to_string(),
+        MetadataArray::Bool(v) => v[index].to_string(),
+        MetadataArray::String(v) => format_bytes(&v[index]),
+        MetadataArray::Array(v) => format_array(&v[index]),
+        MetadataArray::UInt64(v) => v[index].to_string(),
+        MetadataArray::Int64(v) => v[index].to_string(),
+        MetadataArray::Float64(v) => v[index].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gguf_swiss::MetadataArray;
+
+    use crate::text::format_array_difference;
+
+    #[test]
+    fn array_differences() {
+        let strings = |values: &[&str]| {
+            MetadataArray::String(values.iter().map(|v| v.as_bytes().to_vec()).collect())
+        };
+
+        let old = strings(&["a", "b", "c"]);
+        let new = strings(&["a", "x", "c"]);
+        assert_eq!(
+            format_array_difference(&old, &new),
+            "first difference at index 1: \"b\" -> \"x\""
+        );
+
+        let new = strings(&["a", "b", "c", "d"]);
+        assert_eq!(
+            format_array_difference(&old, &new),
+            "first difference at index 3: none -> \"d\""
+        );
+
+        let old = MetadataArray::Float32(vec![1.0, f32::NAN]);
+        let new = MetadataArray::Float32(vec![1.0, -f32::NAN]);
+        assert_eq!(
+            format_array_difference(&old, &new),
+            "first difference at index 1: NaN -> NaN"
+        );
+    }
+}
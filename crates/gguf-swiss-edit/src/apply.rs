@@ -0,0 +1,113 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Error};
+use gguf_swiss::Header;
+use serde_json::Value;
+
+pub fn apply(header: &Header, source: &Path) -> Result<Header, Error> {
+    let text = std::fs::read_to_string(source).context("failed to open header")?;
+
+    let applied: Header = match serde_json::from_str(&text) {
+        Ok(value) => value,
+        Err(_) if has_array_summaries(&text) => {
+            bail!("header contains summarized arrays, export with full arrays")
+        }
+        Err(error) => return Err(error).context("failed to parse header"),
+    };
+
+    // Tensor data is copied as-is, so the tensor information has to match exactly
+    if applied.tensors.len() != header.tensors.len() {
+        bail!("tensor count doesn't match the model");
+    }
+    for (applied, tensor) in applied.tensors.iter().zip(&header.tensors) {
+        if applied.name != tensor.name
+            || applied.tensor_type != tensor.tensor_type
+            || applied.dimensions != tensor.dimensions
+            || applied.offset != tensor.offset
+        {
+            bail!("tensor {:?} doesn't match the model", applied.name);
+        }
+    }
+
+    let value = Header {
+        metadata: applied.metadata,
+        tensors: header.tensors.clone(),
+    };
+    Ok(value)
+}
+
+/// Check if the metadata of a JSON header contains `ArraySummary` values, instead of arrays.
+fn has_array_summaries(text: &str) -> bool {
+    let Ok(value) = serde_json::from_str::<Value>(text) else {
+        return false;
+    };
+
+    value
+        .get("metadata")
+        .and_then(Value::as_object)
+        .is_some_and(|metadata| {
+            metadata
+                .values()
+                .any(|value| value.get("ArraySummary").is_some())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use gguf_swiss::TensorDimensions;
+
+    use crate::{
+        apply::apply,
+        rewrite::{
+            rewrite,
+            tests::{temp_path, test_model},
+        },
+    };
+
+    #[test]
+    fn apply_exported_header() {
+        let (model, json, output) = (
+            temp_path("a.gguf"),
+            temp_path("a.json"),
+            temp_path("b.gguf"),
+        );
+        let (header, data_start) = test_model(&model);
+
+        std::fs::write(&json, serde_json::to_string_pretty(&header).unwrap()).unwrap();
+        let applied = apply(&header, &json).unwrap();
+        rewrite(&model, data_start, &applied, Some(&output)).unwrap();
+
+        assert_eq!(
+            std::fs::read(&model).unwrap(),
+            std::fs::read(&output).unwrap()
+        );
+
+        for path in [model, json, output] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn apply_summarized_header() {
+        let (model, json) = (temp_path("c.gguf"), temp_path("c.json"));
+        let (header, _) = test_model(&model);
+
+        let summarized = r#"{
+            "metadata": { "tokenizer.ggml.tokens": { "ArraySummary": { "type": "String", "len": 2 } } },
+            "tensors": []
+        }"#;
+        std::fs::write(&json, summarized).unwrap();
+        let error = apply(&header, &json).unwrap_err();
+        assert!(error.to_string().contains("summarized arrays"));
+
+        // Changed tensors can't be applied, as the data is copied as-is
+        let mut changed = header.clone();
+        changed.tensors[0].dimensions = TensorDimensions([8, 0, 0, 0]);
+        std::fs::write(&json, serde_json::to_string(&changed).unwrap()).unwrap();
+        assert!(apply(&header, &json).is_err());
+
+        for path in [model, json] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,149 @@
+mod apply;
+mod metadata;
+mod rewrite;
+
+use std::{
+    fs::File,
+    io::Seek,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+use clap::{Parser, Subcommand, ValueEnum};
+use gguf_swiss::{align_offset, MetadataType, TensorType};
+
+fn main() -> Result<(), Error> {
+    let args = Args::parse();
+
+    let path = PathBuf::from(args.path);
+    let output = args.output.map(PathBuf::from);
+
+    // Read the current header, and find where the tensor data starts
+    let mut file = File::open(&path)?;
+    let mut header = gguf_swiss::read_header(&mut file).context("failed to read gguf header")?;
+    let data_start = align_offset(file.stream_position()?);
+    drop(file);
+
+    // Source tensor of every tensor in the edited header, to copy the data from
+    let mut sources = header.tensors.clone();
+
+    // Perform the edit
+    match args.command {
+        Command::Apply { header: source } => header = apply::apply(&header, Path::new(&source))?,
+        Command::Set { key, ty, value } => metadata::set(&mut header, &key, ty, &value)?,
+        Command::Delete { key } => metadata::delete(&mut header, &key)?,
+        Command::Rename { from, to } => metadata::rename(&mut header, &from, &to)?,
+        Command::RenameTensor { from, to } => header.rename_tensor(&from, &to)?,
+        Command::DropTensor { name } => {
+            header.remove_tensor(&name)?;
+            sources.retain(|t| t.name != name);
+        }
+        Command::RetypeTensor { name, ty } => header.retype_tensor(&name, ty.into())?,
+    }
+
+    // Only rearrange tensor data if the tensors changed more than their names
+    let tensors_changed = header.tensors.len() != sources.len()
+        || header
+            .tensors
+            .iter()
+            .zip(&sources)
+            .any(|(t, s)| t.tensor_type != s.tensor_type);
+
+    if tensors_changed {
+        rewrite::rewrite_tensors(&path, data_start, &mut header, &sources, output.as_deref())
+    } else {
+        rewrite::rewrite(&path, data_start, &header, output.as_deref())
+    }
+    .context("failed to write")?;
+
+    Ok(())
+}
+
+/// GGUF Swiss Army Knife, model editing utility.
+///
+/// Edits the model in place, unless an output path is given.
+/// If the new header fits in the space before the tensor data, only the header is rewritten,
+/// otherwise the whole model is copied.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Path to the model to edit.
+    path: String,
+
+    /// Path to write the edited model to, instead of editing in place.
+    ///
+    /// If this is the model itself, it is edited in place.
+    #[arg(long)]
+    output: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Apply a JSON header, as exported by `gguf-swiss-info --format json --full-arrays`.
+    ///
+    /// This replaces all metadata of the model.
+    /// Tensor information must be unchanged, as tensor data is kept as-is.
+    Apply {
+        /// Path to the JSON header to apply.
+        header: String,
+    },
+
+    /// Set a metadata value, adding the key if it doesn't exist.
+    Set {
+        key: String,
+
+        /// Type of the value, replacing the existing type.
+        ///
+        /// One of uint8, int8, uint16, int16, uint32, int32, uint64, int64, float32, float64, bool
+        /// or string.
+        #[arg(long = "type", value_parser = parse_type)]
+        ty: MetadataType,
+
+        value: String,
+    },
+
+    /// Delete a metadata key.
+    Delete { key: String },
+
+    /// Rename a metadata key, keeping its value and position.
+    Rename { from: String, to: String },
+
+    /// Rename a tensor.
+    RenameTensor { from: String, to: String },
+
+    /// Remove a tensor and its data.
+    DropTensor { name: String },
+
+    /// Convert a tensor to another type.
+    ///
+    /// Quantized tensors are dequantized.
+    RetypeTensor {
+        name: String,
+
+        #[arg(long = "type", value_enum)]
+        ty: TargetType,
+    },
+}
+
+/// Tensor types that tensors can be converted to.
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum TargetType {
+    F32,
+    F16,
+}
+
+impl From<TargetType> for TensorType {
+    fn from(value: TargetType) -> Self {
+        match value {
+            TargetType::F32 => TensorType::F32,
+            TargetType::F16 => TensorType::F16,
+        }
+    }
+}
+
+fn parse_type(value: &str) -> Result<MetadataType, String> {
+    value.parse().map_err(|error| format!("{}", error))
+}
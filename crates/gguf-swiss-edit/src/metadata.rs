@@ -0,0 +1,69 @@
+use anyhow::{bail, Error};
+use gguf_swiss::{Header, MetadataType, MetadataValue};
+
+/// Set a metadata value, replacing the existing value in place, or adding it at the end.
+pub fn set(header: &mut Header, key: &str, ty: MetadataType, value: &str) -> Result<(), Error> {
+    let value = MetadataValue::parse(ty, value)?;
+
+    match header.metadata.iter_mut().find(|(k, _)| k == key) {
+        Some((_, existing)) => *existing = value,
+        None => header.metadata.push((key.to_string(), value)),
+    }
+
+    Ok(())
+}
+
+pub fn delete(header: &mut Header, key: &str) -> Result<(), Error> {
+    let Some(index) = header.metadata.iter().position(|(k, _)| k == key) else {
+        bail!("key {:?} not found", key);
+    };
+
+    header.metadata.remove(index);
+    Ok(())
+}
+
+pub fn rename(header: &mut Header, from: &str, to: &str) -> Result<(), Error> {
+    if header.find_metadata(to).is_some() {
+        bail!("key {:?} already exists", to);
+    }
+
+    let Some((key, _)) = header.metadata.iter_mut().find(|(k, _)| k == from) else {
+        bail!("key {:?} not found", from);
+    };
+
+    *key = to.to_string();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use gguf_swiss::{Header, MetadataType, MetadataValue};
+
+    use crate::metadata::{delete, rename, set};
+
+    #[test]
+    fn edit_metadata() {
+        let mut header = Header::default();
+        let keys = |header: &Header| -> Vec<String> {
+            header.metadata.iter().map(|(k, _)| k.clone()).collect()
+        };
+
+        set(&mut header, "a", MetadataType::UInt32, "1").unwrap();
+        set(&mut header, "b", MetadataType::String, "x").unwrap();
+        assert!(set(&mut header, "c", MetadataType::UInt8, "-1").is_err());
+
+        // Replacing keeps the position, and can change the type
+        set(&mut header, "a", MetadataType::Float32, "0.5").unwrap();
+        assert_eq!(keys(&header), ["a", "b"]);
+        assert_eq!(header.metadata[0].1, MetadataValue::Float32(0.5));
+
+        rename(&mut header, "a", "c").unwrap();
+        assert_eq!(keys(&header), ["c", "b"]);
+        assert!(rename(&mut header, "c", "b").is_err());
+        assert!(rename(&mut header, "a", "d").is_err());
+
+        delete(&mut header, "c").unwrap();
+        assert_eq!(keys(&header), ["b"]);
+        assert!(delete(&mut header, "c").is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Error};
+use gguf_swiss::{align_offset, write_padding, write_tensor_data, Header, TensorInfo};
+
+/// Write the model with a new header, copying the tensor data as-is.
+///
+/// Without an output path, or if it is the source itself, the source file is edited in place if
+/// the new header fits before the tensor data, otherwise a copy replaces the source file once
+/// complete.
+pub fn rewrite(
+    source: &Path,
+    source_data_start: u64,
+    header: &Header,
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let output = separate_output(source, output);
+    if output.is_none() {
+        let mut buffer = Vec::new();
+        gguf_swiss::write_header(&mut buffer, header)?;
+
+        // The tensor data has to start at the same position after the new header
+        if align_offset(buffer.len() as u64) == source_data_start {
+            return write_in_place(source, buffer, source_data_start);
+        }
+    }
+
+    let target_path = target_path(source, output);
+
+    println!("writing {}", target_path.display());
+    let mut source_file = File::open(source)?;
+    let mut target = BufWriter::new(File::create(&target_path)?);
+
+    // Write the new header
+    gguf_swiss::write_header(&mut target, header)?;
+    write_padding(&mut target)?;
+
+    // Copy over all tensor data
+    source_file.seek(SeekFrom::Start(source_data_start))?;
+    io::copy(&mut source_file, &mut target).context("failed to copy tensor data")?;
+    target.flush()?;
+    drop(target);
+
+    finish(source, &target_path, output)
+}
+
+/// Write the model with a new header and rearranged tensor data.
+///
+/// `sources` are the tensors in the source model to read the data of every tensor in `header`
+/// from, converting it if the type changed.
+/// Tensor offsets are recalculated.
+/// Like `rewrite`, an output path that is the source itself replaces the source once complete.
+pub fn rewrite_tensors(
+    source: &Path,
+    source_data_start: u64,
+    header: &mut Header,
+    sources: &[TensorInfo],
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let output = separate_output(source, output);
+    header.update_offsets();
+    let target_path = target_path(source, output);
+
+    println!("writing {}", target_path.display());
+    let mut source_file = File::open(source)?;
+    let mut target = BufWriter::new(File::create(&target_path)?);
+
+    gguf_swiss::write_header(&mut target, header)?;
+    let data_start = write_padding(&mut target)?;
+
+    for (tensor, source_tensor) in header.tensors.iter().zip(sources) {
+        // Pad up to the tensor, the data is streamed directly after
+        write_tensor_data(&mut target, data_start, tensor, &[])?;
+
+        if tensor.tensor_type == source_tensor.tensor_type {
+            copy_tensor_data(
+                &mut source_file,
+                source_data_start,
+                source_tensor,
+                &mut target,
+            )?;
+            continue;
+        }
+
+        let from = source_tensor.tensor_type;
+        gguf_swiss::read_tensor_chunks(
+            &mut source_file,
+            source_data_start,
+            source_tensor,
+            CHUNK_SIZE,
+            |data| {
+                let data = gguf_swiss::convert_tensor_data(from, tensor.tensor_type, data)
+                    .with_context(|| format!("failed to convert tensor {:?}", tensor.name))?;
+                target.write_all(&data)?;
+                Ok(())
+            },
+        )?;
+    }
+
+    write_padding(&mut target)?;
+    target.flush()?;
+    drop(target);
+
+    finish(source, &target_path, output)
+}
+
+/// Maximum amount of tensor data to convert at once.
+const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+fn copy_tensor_data(
+    source: &mut File,
+    source_data_start: u64,
+    tensor: &TensorInfo,
+    target: &mut impl Write,
+) -> Result<(), Error> {
+    source.seek(SeekFrom::Start(source_data_start + tensor.offset))?;
+
+    let copied = io::copy(&mut source.take(tensor.size()), target)?;
+    if copied != tensor.size() {
+        bail!("failed to read tensor {:?}, file too short", tensor.name);
+    }
+
+    Ok(())
+}
+
+/// Get the output path, unless it is the source, which would be truncated before being read.
+fn separate_output<'a>(source: &Path, output: Option<&'a Path>) -> Option<&'a Path> {
+    let output = output?;
+
+    let same = match (source.canonicalize(), output.canonicalize()) {
+        (Ok(source), Ok(output)) => source == output,
+        _ => false,
+    };
+    (!same).then_some(output)
+}
+
+/// Get the path to write to, a temporary file next to the source if editing in place.
+fn target_path(source: &Path, output: Option<&Path>) -> PathBuf {
+    match output {
+        Some(output) => output.to_path_buf(),
+        None => {
+            let mut value = source.as_os_str().to_owned();
+            value.push(".tmp");
+            value.into()
+        }
+    }
+}
+
+fn finish(source: &Path, target_path: &Path, output: Option<&Path>) -> Result<(), Error> {
+    if output.is_none() {
+        std::fs::rename(target_path, source).context("failed to replace source")?;
+    }
+
+    Ok(())
+}
+
+fn write_in_place(source: &Path, mut header: Vec<u8>, data_start: u64) -> Result<(), Error> {
+    println!("editing {} in place", source.display());
+
+    header.resize(data_start as usize, 0);
+
+    let mut file = OpenOptions::new().write(true).open(source)?;
+    file.write_all(&header)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+
+    use gguf_swiss::{
+        Header, MetadataArray, MetadataValue, TensorDimensions, TensorInfo, TensorType,
+    };
+
+    use crate::rewrite::{rewrite, rewrite_tensors};
+
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("gguf-swiss-edit-{}-{}", std::process::id(), name))
+    }
+
+    pub(crate) fn test_model(path: &PathBuf) -> (Header, u64) {
+        let header = Header {
+            metadata: vec![
+                (
+                    "general.architecture".to_string(),
+                    MetadataValue::String(b"llama".to_vec()),
+                ),
+                (
+                    "llama.rope.freq_base".to_string(),
+                    MetadataValue::Float32(0.1),
+                ),
+                (
+                    "tokenizer.ggml.tokens".to_string(),
+                    MetadataValue::Array(MetadataArray::String(vec![
+                        b"a".to_vec(),
+                        vec![0xe4, 0xbd],
+                    ])),
+                ),
+            ],
+            tensors: vec![TensorInfo {
+                name: "output.weight".to_string(),
+                tensor_type: TensorType::F32,
+                dimensions: TensorDimensions([4, 2, 0, 0]),
+                offset: 0,
+            }],
+        };
+
+        let mut data = Vec::new();
+        gguf_swiss::write_header(&mut data, &header).unwrap();
+        let data_start = gguf_swiss::align_offset(data.len() as u64);
+        data.resize(data_start as usize, 0);
+        data.extend((0..32).map(|v| v as u8));
+        std::fs::write(path, data).unwrap();
+
+        (header, data_start)
+    }
+
+    fn read_model(path: &PathBuf) -> (Header, Vec<u8>) {
+        let data = std::fs::read(path).unwrap();
+        let header = gguf_swiss::read_header(&mut data.as_slice()).unwrap();
+        (header, data)
+    }
+
+    #[test]
+    fn rewrite_in_place() {
+        let model = temp_path("in-place.gguf");
+        let (mut header, data_start) = test_model(&model);
+        let (_, before) = read_model(&model);
+
+        // Same size header, the file is edited in place
+        header.metadata[1].1 = MetadataValue::Float32(0.5);
+        rewrite(&model, data_start, &header, None).unwrap();
+
+        let (read, after) = read_model(&model);
+        assert_eq!(read, header);
+        assert_eq!(after.len(), before.len());
+        assert_eq!(after[data_start as usize..], before[data_start as usize..]);
+        assert!(!temp_path("in-place.gguf.tmp").exists());
+
+        // A larger header moves the tensor data
+        header.metadata.push((
+            "general.name".to_string(),
+            MetadataValue::String(vec![b'a'; 64]),
+        ));
+        rewrite(&model, data_start, &header, None).unwrap();
+
+        let (read, moved) = read_model(&model);
+        assert_eq!(read, header);
+        assert_eq!(moved[moved.len() - 32..], before[data_start as usize..]);
+
+        // Writing to the source itself replaces it, instead of truncating it first
+        let same = model
+            .parent()
+            .unwrap()
+            .join(".")
+            .join(model.file_name().unwrap());
+        let moved_start = moved.len() as u64 - 32;
+        header.metadata.pop();
+        rewrite(&model, moved_start, &header, Some(&same)).unwrap();
+
+        let (read, after) = read_model(&model);
+        assert_eq!(read, header);
+        assert_eq!(after[after.len() - 32..], before[data_start as usize..]);
+
+        std::fs::remove_file(model).unwrap();
+    }
+
+    #[test]
+    fn rewrite_converted_tensors() {
+        let (model, output) = (temp_path("convert.gguf"), temp_path("convert-out.gguf"));
+        let (header, data_start) = test_model(&model);
+
+        let mut converted = header.clone();
+        converted
+            .retype_tensor("output.weight", TensorType::F16)
+            .unwrap();
+        rewrite_tensors(
+            &model,
+            data_start,
+            &mut converted,
+            &header.tensors,
+            Some(&output),
+        )
+        .unwrap();
+
+        let (read, data) = read_model(&output);
+        assert_eq!(read, converted);
+        let mut buffer = Vec::new();
+        gguf_swiss::write_header(&mut buffer, &converted).unwrap();
+
+        // 8 F16 values, padded to the alignment
+        let converted_start = gguf_swiss::align_offset(buffer.len() as u64) as usize;
+        assert_eq!(data.len(), converted_start + 32);
+        let (_, source) = read_model(&model);
+        let source_values = &source[data_start as usize..];
+        let expected =
+            gguf_swiss::convert_tensor_data(TensorType::F32, TensorType::F16, source_values)
+                .unwrap();
+        assert_eq!(data[converted_start..converted_start + 16], expected);
+
+        // Tensors of the same type are copied as-is
+        let mut renamed = header.clone();
+        renamed.rename_tensor("output.weight", "renamed").unwrap();
+        rewrite_tensors(
+            &model,
+            data_start,
+            &mut renamed,
+            &header.tensors,
+            Some(&output),
+        )
+        .unwrap();
+
+        let (read, data) = read_model(&output);
+        assert_eq!(read, renamed);
+        assert_eq!(data[data.len() - 32..], *source_values);
+
+        for path in [model, output] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{fmt::Write, ops::Range};
+
+use anyhow::{bail, Context, Error};
+use gguf_swiss::{format_bytes, MetadataArray};
+
+/// Which elements of metadata arrays to show.
+#[derive(Debug, Default, Clone)]
+pub struct ArraySelection {
+    pub head: Option<usize>,
+    pub tail: Option<usize>,
+    pub range: Option<Range<usize>>,
+    pub full: bool,
+}
+
+impl ArraySelection {
+    /// Get the sorted, non-overlapping ranges of indices to show for an array of this length.
+    #[allow(clippy::single_range_in_vec_init)]
+    pub fn ranges(&self, len: usize) -> Vec<Range<usize>> {
+        if self.full {
+            return vec![0..len];
+        }
+
+        let mut ranges = Vec::new();
+        if let Some(head) = self.head {
+            ranges.push(0..head.min(len));
+        }
+        if let Some(range) = &self.range {
+            ranges.push(range.start.min(len)..range.end.min(len));
+        }
+        if let Some(tail) = self.tail {
+            ranges.push(len.saturating_sub(tail)..len);
+        }
+
+        // Merge overlapping and adjacent ranges
+        ranges.sort_by_key(|r| r.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for range in ranges.into_iter().filter(|r| !r.is_empty()) {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+
+        merged
+    }
+}
+
+/// Parse an index range in the format `start..end`.
+pub fn parse_range(value: &str) -> Result<Range<usize>, Error> {
+    let (start, end) = value.split_once("..").context("expected \"start..end\"")?;
+    let start = start.parse().context("invalid range start")?;
+    let end = end.parse().context("invalid range end")?;
+
+    if start > end {
+        bail!("range start is after its end");
+    }
+
+    Ok(start..end)
+}
+
+/// Get the elements of an array in a range, as an array of the same type.
+pub fn slice(array: &MetadataArray, range: Range<usize>) -> MetadataArray {
+    match array {
+        MetadataArray::UInt8(v) => MetadataArray::UInt8(v[range].to_vec()),
+        MetadataArray::Int8(v) => MetadataArray::Int8(v[range].to_vec()),
+        MetadataArray::UInt16(v) => MetadataArray::UInt16(v[range].to_vec()),
+        MetadataArray::Int16(v) => MetadataArray::Int16(v[range].to_vec()),
+        MetadataArray::UInt32(v) => MetadataArray::UInt32(v[range].to_vec()),
+        MetadataArray::Int32(v) => MetadataArray::Int32(v[range].to_vec()),
+        MetadataArray::Float32(v) => MetadataArray::Float32(v[range].to_vec()),
+        MetadataArray::Bool(v) => MetadataArray::Bool(v[range].to_vec()),
+        MetadataArray::String(v) => MetadataArray::String(v[range].to_vec()),
+        MetadataArray::Array(v) => MetadataArray::Array(v[range].to_vec()),
+        MetadataArray::UInt64(v) => MetadataArray::UInt64(v[range].to_vec()),
+        MetadataArray::Int64(v) => MetadataArray::Int64(v[range].to_vec()),
+        MetadataArray::Float64(v) => MetadataArray::Float64(v[range].to_vec()),
+    }
+}
+
+/// Format a single element of an array.
+///
+/// Nested arrays are formatted inline, with the same selection applied.
+pub fn format_element(array: &MetadataArray, index: usize, selection: &ArraySelection) -> String {
+    match array {
+        MetadataArray::UInt8(v) => v[index].to_string(),
+        MetadataArray::Int8(v) => v[index].to_string(),
+        MetadataArray::UInt16(v) => v[index].to_string(),
+        MetadataArray::Int16(v) => v[index].to_string(),
+        MetadataArray::UInt32(v) => v[index].to_string(),
+        MetadataArray::Int32(v) => v[index].to_string(),
+        MetadataArray::Float32(v) => v[index].to_string(),
+        MetadataArray::Bool(v) => v[index].to_string(),
+        MetadataArray::String(v) => format_bytes(&v[index]),
+        MetadataArray::Array(v) => format_inline(&v[index], selection),
+        MetadataArray::UInt64(v) => v[index].to_string(),
+        MetadataArray::Int64(v) => v[index].to_string(),
+        MetadataArray::Float64(v) => v[index].to_string(),
+    }
+}
+
+/// Format the selected elements of an array on a single line.
+pub fn format_inline(array: &MetadataArray, selection: &ArraySelection) -> String {
+    let mut value = format!("[{:?}; {}]", array.ty(), array.len());
+
+    let ranges = selection.ranges(array.len());
+    if ranges.is_empty() {
+        return value;
+    }
+
+    let mut elements = Vec::new();
+    let mut next = 0;
+    for range in ranges {
+        if range.start != next {
+            elements.push("...".to_string());
+        }
+        next = range.end;
+
+        for i in range {
+            elements.push(format_element(array, i, selection));
+        }
+    }
+    if next != array.len() {
+        elements.push("...".to_string());
+    }
+
+    write!(value, " [{}]", elements.join(", ")).unwrap();
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::array::{parse_range, ArraySelection};
+
+    #[test]
+    fn array_selection_ranges() {
+        let selection = ArraySelection {
+            head: Some(3),
+            tail: Some(2),
+            range: Some(2..5),
+            full: false,
+        };
+        assert_eq!(selection.ranges(10), vec![0..5, 8..10]);
+        assert_eq!(selection.ranges(4), vec![0..4]);
+        assert!(ArraySelection::default().ranges(10).is_empty());
+    }
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(parse_range("100..200").unwrap(), 100..200);
+        assert_eq!(parse_range("5..5").unwrap(), 5..5);
+        assert!(parse_range("200..100").is_err());
+        assert!(parse_range("100").is_err());
+    }
+}
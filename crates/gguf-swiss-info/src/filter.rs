@@ -0,0 +1,95 @@
+use anyhow::{Context, Error};
+use gguf_swiss::{glob_match, Header, MetadataValue};
+
+/// Glob filters for metadata keys and tensor names.
+///
+/// An empty list of patterns matches everything.
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub keys: Vec<String>,
+    pub tensors: Vec<String>,
+}
+
+impl Filter {
+    /// Remove metadata and tensors that don't match from the header.
+    pub fn apply(&self, header: &mut Header) {
+        header
+            .metadata
+            .retain(|(key, _)| matches_any(&self.keys, key));
+        header
+            .tensors
+            .retain(|tensor| matches_any(&self.tensors, &tensor.name));
+    }
+}
+
+/// Find the value of a single metadata key, for `--get`.
+pub fn get_value<'a>(header: &'a Header, key: &str) -> Result<&'a MetadataValue, Error> {
+    header
+        .find_metadata(key)
+        .with_context(|| format!("key {:?} not found", key))
+}
+
+fn matches_any(patterns: &[String], value: &str) -> bool {
+    patterns.is_empty() || patterns.iter().any(|p| glob_match(p, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        filter::{get_value, Filter},
+        text::{print_raw, tests::test_info},
+    };
+
+    #[test]
+    fn filter_header() {
+        let mut header = test_info().header;
+        let filter = Filter {
+            keys: vec!["general.*".to_string(), "tokens".to_string()],
+            tensors: vec!["blk.*".to_string()],
+        };
+        filter.apply(&mut header);
+
+        let keys: Vec<_> = header.metadata.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "general.architecture",
+                "general.name",
+                "general.description",
+                "tokens"
+            ]
+        );
+        assert!(header.tensors.is_empty());
+
+        let mut header = test_info().header;
+        let filter = Filter {
+            keys: vec!["*.name".to_string()],
+            tensors: vec!["*.weight".to_string(), "nope".to_string()],
+        };
+        filter.apply(&mut header);
+        assert_eq!(header.metadata.len(), 1);
+        assert_eq!(header.metadata[0].0, "general.name");
+        assert_eq!(header.tensors.len(), 1);
+
+        // Empty filters keep everything
+        let mut header = test_info().header;
+        Filter::default().apply(&mut header);
+        assert_eq!(header, test_info().header);
+    }
+
+    #[test]
+    fn get_values() {
+        let header = test_info().header;
+
+        let mut out = Vec::new();
+        print_raw(&mut out, get_value(&header, "general.description").unwrap()).unwrap();
+        assert_eq!(out, b"a\tb\nc\n");
+
+        let mut out = Vec::new();
+        print_raw(&mut out, get_value(&header, "tokens").unwrap()).unwrap();
+        assert_eq!(out, b"1\n2\n3\n");
+
+        let error = get_value(&header, "general.missing").unwrap_err();
+        assert_eq!(error.to_string(), "key \"general.missing\" not found");
+    }
+}
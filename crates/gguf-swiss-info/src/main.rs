@@ -1,63 +1,91 @@
-use std::fs::File;
+mod array;
+mod filter;
+mod report;
+mod sizes;
+mod stats;
+mod text;
+mod tree;
+
+use std::{
+    fs::File,
+    io::{self, Seek},
+    ops::Range,
+};
 
 use anyhow::{bail, Context, Error};
-use clap::Parser;
-use gguf_swiss::{Header, MetadataValue};
+use clap::{Parser, ValueEnum};
+use gguf_swiss::{align_offset, Header};
+
+use crate::{
+    array::ArraySelection, filter::Filter, report::ModelCard, sizes::SizeReport,
+    stats::TensorStats, text::TextOptions,
+};
 
 fn main() -> Result<(), Error> {
     let args = Args::parse();
 
+    if args.tree && !matches!(args.format, Format::Markdown | Format::Plain) {
+        bail!("--tree is only supported with the markdown and plain formats");
+    }
+
     let mut file = File::open(args.path)?;
+    let mut out = io::stdout().lock();
 
-    let header = gguf_swiss::read_header(&mut file).context("failed to read gguf header")?;
+    let mut header = gguf_swiss::read_header(&mut file).context("failed to read gguf header")?;
+    let data_start = align_offset(file.stream_position()?);
 
-    let architecture = header.find_metadata("general.architecture");
-    let Some(MetadataValue::String(architecture)) = architecture else {
-        bail!("required key \"general.architecture\" missing from model")
-    };
-    let architecture = String::from_utf8(architecture.clone())?;
-
-    // Extract important information KVs
-    let name = get_metadata(&header, "general.name", "")?;
-    let author = get_metadata(&header, "general.author", "")?;
-    let url = get_metadata(&header, "general.url", "")?;
-    let description = get_metadata(&header, "general.description", "")?;
-    let license = get_metadata(&header, "general.license", "")?;
-
-    // Print information
-    println!("# {} - GGUF Model Information", name);
-    println!("Name: {}", name);
-    println!("Author: {}", author);
-    println!("URL: {}", url);
-    println!("Description: {}", description);
-    println!("License: {}", license);
-    println!("Architecture: {}", architecture);
-    println!("GGUF Version: {}", 3);
-    println!("Metadata KV Count: {}", header.metadata.len());
-    println!("Tensor Count: {}", header.tensors.len());
-
-    println!("\n## Metadata");
-    for (key, value) in header.metadata {
-        let value = format_value(&value);
-        println!("`{}`: {}", key, value);
+    if let Some(key) = &args.get {
+        let value = filter::get_value(&header, key)?;
+        text::print_raw(&mut out, value)?;
+        return Ok(());
     }
 
-    println!("\n## Tensors");
-    let mut tensors: Vec<_> = header.tensors.iter().collect();
-    tensors.sort_by_key(|v| v.name.as_str());
-    for tensor in tensors {
-        println!(
-            "`{}`: `{{type: {:?}, dimensions: {}, offset: {}}}`",
-            tensor.name, tensor.tensor_type, tensor.dimensions, tensor.offset
-        );
-    }
+    // The model card and sizes are always taken from the full header, as sizes are reported
+    // relative to the whole file
+    let card = ModelCard::from_header(&header)?;
+    let sizes = if args.sizes {
+        let file_size = file.metadata()?.len();
+        Some(SizeReport::new(&header, file_size))
+    } else {
+        None
+    };
+
+    let filter = Filter {
+        keys: args.key,
+        tensors: args.tensor,
+    };
+    filter.apply(&mut header);
+
+    // Reading tensor data is expensive, so only do it if requested
+    let stats = if args.stats || args.histogram.is_some() {
+        let stats = stats::collect(&mut file, data_start, &header, args.histogram);
+        Some(stats)
+    } else {
+        None
+    };
+
+    let info = Info {
+        card,
+        header,
+        stats,
+        sizes,
+    };
+
+    let options = TextOptions {
+        selection: ArraySelection {
+            head: args.array_head,
+            tail: args.array_tail,
+            range: args.array_range,
+            full: args.full_arrays,
+        },
+        tree: args.tree,
+    };
 
-    // Generation metadata for debugging
-    println!("\n## GGUF Swiss");
-    println!(
-        "Model information generated by {} {}.",
-        PKG_NAME, PKG_VERSION
-    );
+    match args.format {
+        Format::Markdown => text::print_markdown(&mut out, &info, &options)?,
+        Format::Plain => text::print_plain(&mut out, &info, &options)?,
+        format => report::print(&mut out, &info, format, &options.selection)?,
+    }
 
     Ok(())
 }
@@ -68,42 +96,100 @@ fn main() -> Result<(), Error> {
 struct Args {
     /// Path to the model to read.
     path: String,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = Format::Markdown)]
+    format: Format,
+
+    /// Include the full contents of metadata arrays, instead of only their type and length.
+    ///
+    /// This is required for JSON output to be importable again.
+    #[arg(long)]
+    full_arrays: bool,
+
+    /// Show the first N elements of metadata arrays.
+    #[arg(long, value_name = "N")]
+    array_head: Option<usize>,
+
+    /// Show the last N elements of metadata arrays.
+    #[arg(long, value_name = "N")]
+    array_tail: Option<usize>,
+
+    /// Show the elements of metadata arrays in an index range, for example `100..200`.
+    #[arg(long, value_name = "START..END", value_parser = array::parse_range)]
+    array_range: Option<Range<usize>>,
+
+    /// Only show metadata keys matching this glob pattern, for example `general.*`.
+    ///
+    /// Can be passed multiple times.
+    #[arg(long, value_name = "GLOB")]
+    key: Vec<String>,
+
+    /// Only show tensors with names matching this glob pattern, for example `blk.*.attn_*`.
+    ///
+    /// Can be passed multiple times.
+    #[arg(long, value_name = "GLOB")]
+    tensor: Vec<String>,
+
+    /// Print only the raw value of a single metadata key, for use in scripts.
+    ///
+    /// Strings are printed without quotes, and array elements one per line.
+    #[arg(long, value_name = "KEY")]
+    get: Option<String>,
+
+    /// Group tensors by layer, collapsing tensors repeated in every layer into a single template.
+    ///
+    /// Layers that deviate from the template are listed separately.
+    /// Only supported with the markdown and plain formats.
+    #[arg(long)]
+    tree: bool,
+
+    /// Show a breakdown of the model's size, by tensor type and tensor group, and estimated memory
+    /// requirements.
+    ///
+    /// Always covers the whole model, ignoring `--key` and `--tensor`.
+    #[arg(long)]
+    sizes: bool,
+
+    /// Read all tensor data, and show statistics of the values of every tensor.
+    ///
+    /// Quantized tensors are dequantized first.
+    #[arg(long)]
+    stats: bool,
+
+    /// Include a histogram with this many bins in the statistics, implies `--stats`.
+    #[arg(long, value_name = "BINS")]
+    histogram: Option<usize>,
 }
 
-const PKG_NAME: &str = env!("CARGO_PKG_NAME");
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Format {
+    Markdown,
+    Plain,
+    Json,
+    Yaml,
+    Toml,
+}
 
-const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Everything gathered about the model, to be printed.
+struct Info {
+    card: ModelCard,
 
-fn get_metadata(header: &Header, key: &str, default: &str) -> Result<String, Error> {
-    let Some(value) = header.find_metadata(key) else {
-        return Ok(default.to_string());
-    };
+    /// The header, with filtered out metadata and tensors removed.
+    header: Header,
 
-    let MetadataValue::String(value) = value else {
-        return Ok(default.to_string());
-    };
+    /// Statistics of every tensor, in the same order as the header's tensors, if requested.
+    stats: Option<Vec<Option<TensorStats>>>,
 
-    let value = String::from_utf8(value.clone())?;
-    Ok(value)
+    sizes: Option<SizeReport>,
 }
 
-fn format_value(value: &MetadataValue) -> String {
-    match value {
-        MetadataValue::UInt8(value) => value.to_string(),
-        MetadataValue::Int8(value) => value.to_string(),
-        MetadataValue::UInt16(value) => value.to_string(),
-        MetadataValue::Int16(value) => value.to_string(),
-        MetadataValue::UInt32(value) => value.to_string(),
-        MetadataValue::Int32(value) => value.to_string(),
-        MetadataValue::Float32(value) => value.to_string(),
-        MetadataValue::Bool(value) => value.to_string(),
-        MetadataValue::String(value) => {
-            let value = String::from_utf8_lossy(value);
-            format!("{:?}", value)
-        }
-        MetadataValue::Array(value) => format!("[{:?}; {}]", value.ty(), value.len()),
-        MetadataValue::UInt64(value) => value.to_string(),
-        MetadataValue::Int64(value) => value.to_string(),
-        MetadataValue::Float64(value) => value.to_string(),
+impl Info {
+    fn tensor_stats(&self, index: usize) -> Option<&TensorStats> {
+        self.stats.as_ref().and_then(|stats| stats[index].as_ref())
     }
 }
+
+const PKG_NAME: &str = env!("CARGO_PKG_NAME");
+
+const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
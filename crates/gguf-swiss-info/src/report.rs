@@ -0,0 +1,281 @@
+use std::io::Write;
+
+use anyhow::{bail, Error};
+use gguf_swiss::{
+    Header, MetadataArray, MetadataType, MetadataValue, TensorDimensions, TensorType,
+};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    array::{self, ArraySelection},
+    sizes::SizeReport,
+    stats::TensorStats,
+    Format, Info,
+};
+
+/// Print the header in a structured format.
+///
+/// The `metadata` and `tensors` fields match the format used by `gguf-swiss-edit apply`, so JSON
+/// reports can be imported again.
+/// Unless the selection includes full arrays, array contents are replaced with an `ArraySummary`
+/// of their type, length, and selected elements, which can't be imported.
+pub fn print(
+    out: &mut impl Write,
+    info: &Info,
+    format: Format,
+    selection: &ArraySelection,
+) -> Result<(), Error> {
+    let header = &info.header;
+    let tensors = header
+        .tensors
+        .iter()
+        .enumerate()
+        .map(|(i, tensor)| TensorReport {
+            name: &tensor.name,
+            tensor_type: tensor.tensor_type,
+            dimensions: tensor.dimensions,
+            offset: tensor.offset,
+            size: tensor.size(),
+            stats: info.tensor_stats(i),
+        })
+        .collect();
+
+    let report = Report {
+        model: &info.card,
+        metadata: Metadata {
+            entries: &header.metadata,
+            selection,
+        },
+        tensors,
+        sizes: info.sizes.as_ref(),
+    };
+
+    let text = match format {
+        Format::Json => serde_json::to_string_pretty(&report)?,
+        Format::Yaml => serde_yaml::to_string(&report)?,
+        Format::Toml => {
+            check_toml(&header.metadata, selection)?;
+            toml::to_string(&report)?
+        }
+        _ => bail!("not a structured format"),
+    };
+    writeln!(out, "{}", text.trim_end())?;
+
+    Ok(())
+}
+
+/// Check that the selected metadata has no integers above `i64::MAX`, which TOML can't represent.
+fn check_toml(
+    metadata: &[(String, MetadataValue)],
+    selection: &ArraySelection,
+) -> Result<(), Error> {
+    let too_large = |values: &[u64]| values.iter().any(|v| *v > i64::MAX as u64);
+
+    for (key, value) in metadata {
+        let invalid = match value {
+            MetadataValue::UInt64(value) => too_large(&[*value]),
+            MetadataValue::Array(MetadataArray::UInt64(values)) => selection
+                .ranges(values.len())
+                .into_iter()
+                .any(|range| too_large(&values[range])),
+            _ => false,
+        };
+
+        if invalid {
+            bail!(
+                "metadata key {:?} has a value above {}, which TOML can't represent, use another format",
+                key,
+                i64::MAX
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Summary of the important model information.
+#[derive(Serialize)]
+pub struct ModelCard {
+    pub name: String,
+    pub author: String,
+    pub url: String,
+    pub description: String,
+    pub license: String,
+    pub architecture: String,
+}
+
+impl ModelCard {
+    pub fn from_header(header: &Header) -> Result<Self, Error> {
+        let architecture = header.find_metadata("general.architecture");
+        let Some(MetadataValue::String(architecture)) = architecture else {
+            bail!("required key \"general.architecture\" missing from model")
+        };
+        let architecture = String::from_utf8(architecture.clone())?;
+
+        let value = Self {
+            name: get_metadata(header, "general.name", "")?,
+            author: get_metadata(header, "general.author", "")?,
+            url: get_metadata(header, "general.url", "")?,
+            description: get_metadata(header, "general.description", "")?,
+            license: get_metadata(header, "general.license", "")?,
+            architecture,
+        };
+        Ok(value)
+    }
+}
+
+fn get_metadata(header: &Header, key: &str, default: &str) -> Result<String, Error> {
+    let Some(value) = header.find_metadata(key) else {
+        return Ok(default.to_string());
+    };
+
+    let MetadataValue::String(value) = value else {
+        return Ok(default.to_string());
+    };
+
+    let value = String::from_utf8(value.clone())?;
+    Ok(value)
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    model: &'a ModelCard,
+    metadata: Metadata<'a>,
+    tensors: Vec<TensorReport<'a>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sizes: Option<&'a SizeReport>,
+}
+
+#[derive(Serialize)]
+struct TensorReport<'a> {
+    name: &'a str,
+    tensor_type: TensorType,
+    dimensions: TensorDimensions,
+    offset: u64,
+
+    /// Size of the tensor's values in bytes.
+    size: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<&'a TensorStats>,
+}
+
+struct Metadata<'a> {
+    entries: &'a [(String, MetadataValue)],
+    selection: &'a ArraySelection,
+}
+
+impl Serialize for Metadata<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entries = self.entries.iter().map(|(key, value)| {
+            let value = match value {
+                MetadataValue::Array(array) if !self.selection.full => {
+                    let elements = self
+                        .selection
+                        .ranges(array.len())
+                        .into_iter()
+                        .map(|range| ArrayElements {
+                            start: range.start,
+                            values: array::slice(array, range),
+                        })
+                        .collect();
+
+                    ReportValue::Summary(ArraySummary {
+                        ty: array.ty(),
+                        len: array.len(),
+                        elements,
+                    })
+                }
+                value => ReportValue::Value(value),
+            };
+
+            (key, value)
+        });
+
+        serializer.collect_map(entries)
+    }
+}
+
+#[derive(Serialize)]
+enum ReportValue<'a> {
+    #[serde(rename = "ArraySummary")]
+    Summary(ArraySummary),
+    #[serde(untagged)]
+    Value(&'a MetadataValue),
+}
+
+#[derive(Serialize)]
+struct ArraySummary {
+    #[serde(rename = "type")]
+    ty: MetadataType,
+    len: usize,
+
+    /// Selected ranges of elements.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    elements: Vec<ArrayElements>,
+}
+
+#[derive(Serialize)]
+struct ArrayElements {
+    /// Index of the first element.
+    start: usize,
+    values: MetadataArray,
+}
+
+#[cfg(test)]
+mod tests {
+    use gguf_swiss::{MetadataArray, MetadataValue};
+
+    use crate::{array::ArraySelection, report::print, text::tests::test_info, Format};
+
+    fn print_string(format: Format, selection: &ArraySelection) -> String {
+        let mut out = Vec::new();
+        print(&mut out, &test_info(), format, selection).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn structured_output() {
+        let selection = ArraySelection::default();
+
+        let json = print_string(Format::Json, &selection);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["model"]["description"], "a\tb\nc");
+        assert_eq!(value["metadata"]["tokens"]["ArraySummary"]["len"], 3);
+        assert_eq!(value["tensors"][0]["dimensions"], serde_json::json!([4, 2]));
+
+        let yaml = print_string(Format::Yaml, &selection);
+        let value: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(value["model"]["architecture"].as_str(), Some("llama"));
+        assert_eq!(value["tensors"][0]["size"].as_u64(), Some(32));
+
+        let toml = print_string(Format::Toml, &selection);
+        let value: toml::Table = toml.parse().unwrap();
+        assert_eq!(value["model"]["name"].as_str(), Some("Test"));
+        assert_eq!(value["tensors"][0]["name"].as_str(), Some("output.weight"));
+    }
+
+    #[test]
+    fn toml_large_integers() {
+        let mut info = test_info();
+        let large = MetadataValue::Array(MetadataArray::UInt64(vec![1, u64::MAX]));
+        info.header.metadata[3].1 = large;
+
+        // The large value is only an error if it is part of the output
+        let mut out = Vec::new();
+        let summary = ArraySelection {
+            head: Some(1),
+            ..Default::default()
+        };
+        print(&mut out, &info, Format::Toml, &summary).unwrap();
+
+        let full = ArraySelection {
+            full: true,
+            ..Default::default()
+        };
+        let error = print(&mut out, &info, Format::Toml, &full).unwrap_err();
+        assert!(error.to_string().contains("metadata key \"tokens\""));
+        print(&mut out, &info, Format::Json, &full).unwrap();
+    }
+}
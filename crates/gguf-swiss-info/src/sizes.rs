@@ -0,0 +1,217 @@
+use gguf_swiss::{align_offset, Header, TensorInfo, TensorType};
+use serde::Serialize;
+
+/// Breakdown of the size of a model.
+#[derive(Serialize, Debug)]
+pub struct SizeReport {
+    /// Size of the whole file in bytes.
+    pub file_size: u64,
+
+    /// Size of all tensor data in bytes.
+    pub tensor_size: u64,
+
+    /// Total amount of parameters, the scalars of all tensors.
+    pub parameters: u64,
+
+    /// Average bits per parameter of the tensor data.
+    pub bits_per_parameter: f64,
+
+    /// Size of the embedding tensors in bytes, see `is_embedding`.
+    pub embedding_size: u64,
+
+    /// Size of all other tensors in bytes.
+    pub body_size: u64,
+
+    pub types: Vec<SizeEntry>,
+
+    /// Sizes by tensor group, every layer is a group, other tensors are grouped by their prefix.
+    pub groups: Vec<SizeEntry>,
+
+    pub memory: MemoryEstimate,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SizeEntry {
+    pub name: String,
+    pub tensors: u64,
+    pub parameters: u64,
+    pub size: u64,
+
+    /// Percentage of the whole file.
+    pub percentage: f64,
+}
+
+/// Estimated memory required to load the model's weights.
+///
+/// This does not include runtime state like the KV cache or compute buffers, which depend on the
+/// context size and inference engine.
+#[derive(Serialize, Debug)]
+pub struct MemoryEstimate {
+    /// Loading the tensors as-is, as llama.cpp does, each aligned in memory.
+    pub native: u64,
+
+    /// Loading the model with all tensors as F16.
+    pub f16: u64,
+
+    /// Loading the model with all tensors as F32.
+    pub f32: u64,
+}
+
+impl SizeReport {
+    pub fn new(header: &Header, file_size: u64) -> Self {
+        let mut types: Vec<(TensorType, Vec<&TensorInfo>)> = Vec::new();
+        let mut groups: Vec<(String, Vec<&TensorInfo>)> = Vec::new();
+
+        for tensor in &header.tensors {
+            match types.iter_mut().find(|(ty, _)| *ty == tensor.tensor_type) {
+                Some((_, tensors)) => tensors.push(tensor),
+                None => types.push((tensor.tensor_type, vec![tensor])),
+            }
+
+            let group = tensor_group(&tensor.name);
+            match groups.iter_mut().find(|(name, _)| *name == group) {
+                Some((_, tensors)) => tensors.push(tensor),
+                None => groups.push((group.to_string(), vec![tensor])),
+            }
+        }
+
+        let entry = |name: String, tensors: &[&TensorInfo]| {
+            let size = tensors.iter().map(|t| t.size()).sum::<u64>();
+            SizeEntry {
+                name,
+                tensors: tensors.len() as u64,
+                parameters: tensors.iter().map(|t| t.dimensions.total()).sum(),
+                size,
+                percentage: size as f64 / file_size.max(1) as f64 * 100.0,
+            }
+        };
+        let types = types
+            .into_iter()
+            .map(|(ty, tensors)| entry(format!("{:?}", ty), &tensors))
+            .collect();
+        let groups = groups
+            .into_iter()
+            .map(|(group, tensors)| entry(group, &tensors))
+            .collect();
+
+        let tensor_size = header.tensors.iter().map(|t| t.size()).sum();
+        let parameters = header.tensors.iter().map(|t| t.dimensions.total()).sum();
+        let embedding_size = header
+            .tensors
+            .iter()
+            .filter(|t| is_embedding(&t.name))
+            .map(|t| t.size())
+            .sum();
+
+        Self {
+            file_size,
+            tensor_size,
+            parameters,
+            bits_per_parameter: tensor_size as f64 * 8.0 / parameters.max(1) as f64,
+            embedding_size,
+            body_size: tensor_size - embedding_size,
+            types,
+            groups,
+            memory: MemoryEstimate {
+                native: header.tensors.iter().map(|t| align_offset(t.size())).sum(),
+                f16: parameters * 2,
+                f32: parameters * 4,
+            },
+        }
+    }
+}
+
+/// Check if a tensor is an embedding, token embeddings and their norm, token type embeddings, or
+/// position embeddings.
+///
+/// This only recognizes the standard GGUF tensor names, as used by llama.cpp.
+fn is_embedding(name: &str) -> bool {
+    const PREFIXES: [&str; 4] = [
+        "token_embd.",
+        "token_embd_norm.",
+        "token_types.",
+        "position_embd.",
+    ];
+    PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// Get the group a tensor belongs to, `blk.N` for layers, or the first part of the name.
+fn tensor_group(name: &str) -> &str {
+    let mut parts = name.splitn(3, '.');
+    let first = parts.next().unwrap_or(name);
+
+    match (first, parts.next()) {
+        ("blk", Some(index)) if index.parse::<u64>().is_ok() => {
+            &name[..first.len() + 1 + index.len()]
+        }
+        _ => first,
+    }
+}
+
+/// Format a size in bytes for humans.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gguf_swiss::{Header, TensorDimensions, TensorInfo, TensorType};
+
+    use crate::sizes::{format_size, is_embedding, tensor_group, SizeReport};
+
+    #[test]
+    fn tensor_groups() {
+        assert_eq!(tensor_group("blk.12.attn_norm.weight"), "blk.12");
+        assert_eq!(tensor_group("token_embd.weight"), "token_embd");
+        assert_eq!(tensor_group("blk.x.weight"), "blk");
+        assert_eq!(tensor_group("output"), "output");
+    }
+
+    #[test]
+    fn embeddings_and_memory() {
+        assert!(is_embedding("token_embd.weight"));
+        assert!(is_embedding("position_embd.weight"));
+        assert!(!is_embedding("token_embd"));
+        assert!(!is_embedding("output.weight"));
+
+        let tensor = |name: &str, width| TensorInfo {
+            name: name.to_string(),
+            tensor_type: TensorType::F32,
+            dimensions: TensorDimensions([width, 0, 0, 0]),
+            offset: 0,
+        };
+        let header = Header {
+            metadata: Vec::new(),
+            tensors: vec![
+                tensor("token_embd.weight", 16),
+                tensor("output_norm.weight", 3),
+            ],
+        };
+
+        let report = SizeReport::new(&header, 4096);
+        assert_eq!(report.embedding_size, 64);
+        assert_eq!(report.body_size, 12);
+        assert_eq!(report.memory.native, 64 + 32);
+        assert_eq!(report.memory.f16, 38);
+    }
+
+    #[test]
+    fn format_sizes() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.50 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.00 GiB");
+    }
+}
@@ -0,0 +1,249 @@
+use std::fs::File;
+
+use anyhow::Error;
+use gguf_swiss::{Header, TensorInfo};
+use serde::Serialize;
+
+/// Statistics of the values of a tensor.
+///
+/// Min, max, mean, std and L2 norm only take finite values into account.
+#[derive(Serialize, Debug, Clone)]
+pub struct TensorStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std: f64,
+    pub l2_norm: f64,
+
+    /// Fraction of values that are exactly zero.
+    pub zeros: f64,
+
+    pub nan_count: u64,
+    pub inf_count: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub histogram: Option<Histogram>,
+}
+
+/// Histogram of finite values, with equally sized bins between min and max.
+#[derive(Serialize, Debug, Clone)]
+pub struct Histogram {
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    /// Range of values for a bin.
+    pub fn bin_range(&self, stats: &TensorStats, bin: usize) -> (f64, f64) {
+        let width = (stats.max - stats.min) / self.counts.len() as f64;
+        let start = stats.min + width * bin as f64;
+        (start, start + width)
+    }
+
+    fn add(&mut self, values: &[f32], stats: &TensorStats) {
+        let bins = self.counts.len();
+        let width = stats.max - stats.min;
+
+        for value in values.iter().filter(|v| v.is_finite()) {
+            let position = if width > 0.0 {
+                (*value as f64 - stats.min) / width
+            } else {
+                0.0
+            };
+
+            let bin = ((position * bins as f64) as usize).min(bins - 1);
+            self.counts[bin] += 1;
+        }
+    }
+}
+
+/// Maximum amount of tensor data to read at once.
+const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Read and calculate statistics for every tensor.
+///
+/// Tensor data is read in chunks, and read a second time if a histogram is requested.
+/// Tensors that can't be decoded are reported on stderr and skipped.
+pub fn collect(
+    file: &mut File,
+    data_start: u64,
+    header: &Header,
+    histogram_bins: Option<usize>,
+) -> Vec<Option<TensorStats>> {
+    let mut stats = Vec::new();
+
+    for tensor in &header.tensors {
+        match tensor_stats(file, data_start, tensor, histogram_bins) {
+            Ok(value) => stats.push(Some(value)),
+            Err(error) => {
+                eprintln!("skipping statistics for {:?}: {:#}", tensor.name, error);
+                stats.push(None);
+            }
+        }
+    }
+
+    stats
+}
+
+fn tensor_stats(
+    file: &mut File,
+    data_start: u64,
+    tensor: &TensorInfo,
+    histogram_bins: Option<usize>,
+) -> Result<TensorStats, Error> {
+    let mut accumulator = Accumulator::default();
+    read_values(file, data_start, tensor, |values| accumulator.add(values))?;
+    let mut stats = accumulator.finish();
+
+    if let Some(bins) = histogram_bins.filter(|bins| *bins > 0) {
+        let mut histogram = Histogram {
+            counts: vec![0; bins],
+        };
+        read_values(file, data_start, tensor, |values| {
+            histogram.add(values, &stats)
+        })?;
+        stats.histogram = Some(histogram);
+    }
+
+    Ok(stats)
+}
+
+fn read_values(
+    file: &mut File,
+    data_start: u64,
+    tensor: &TensorInfo,
+    mut f: impl FnMut(&[f32]),
+) -> Result<(), Error> {
+    gguf_swiss::read_tensor_chunks(file, data_start, tensor, CHUNK_SIZE, |data| {
+        let values = gguf_swiss::dequantize(tensor.tensor_type, data)?;
+        f(&values);
+        Ok(())
+    })
+}
+
+/// Statistics calculated incrementally, over chunks of values.
+///
+/// The variance uses Welford's method, which stays accurate for values far from zero.
+#[derive(Debug)]
+struct Accumulator {
+    min: f64,
+    max: f64,
+    mean: f64,
+    m2: f64,
+    sum_squares: f64,
+    finite: u64,
+    total: u64,
+    zeros: u64,
+    nan_count: u64,
+    inf_count: u64,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self {
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+            sum_squares: 0.0,
+            finite: 0,
+            total: 0,
+            zeros: 0,
+            nan_count: 0,
+            inf_count: 0,
+        }
+    }
+}
+
+impl Accumulator {
+    fn add(&mut self, values: &[f32]) {
+        self.total += values.len() as u64;
+
+        for value in values {
+            if value.is_nan() {
+                self.nan_count += 1;
+                continue;
+            }
+            if value.is_infinite() {
+                self.inf_count += 1;
+                continue;
+            }
+
+            let value = *value as f64;
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+            self.sum_squares += value * value;
+            self.finite += 1;
+
+            let delta = value - self.mean;
+            self.mean += delta / self.finite as f64;
+            self.m2 += delta * (value - self.mean);
+
+            if value == 0.0 {
+                self.zeros += 1;
+            }
+        }
+    }
+
+    fn finish(self) -> TensorStats {
+        let (min, max, mean, std) = if self.finite != 0 {
+            let variance = self.m2 / self.finite as f64;
+            (self.min, self.max, self.mean, variance.sqrt())
+        } else {
+            (f64::NAN, f64::NAN, f64::NAN, f64::NAN)
+        };
+
+        TensorStats {
+            min,
+            max,
+            mean,
+            std,
+            l2_norm: self.sum_squares.sqrt(),
+            zeros: self.zeros as f64 / self.total.max(1) as f64,
+            nan_count: self.nan_count,
+            inf_count: self.inf_count,
+            histogram: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::{Accumulator, Histogram};
+
+    #[test]
+    fn calculate_stats() {
+        let values = [1.0, -1.0, 0.0, 2.0, f32::NAN, f32::INFINITY];
+
+        // Split over chunks, like tensor data is read
+        let mut accumulator = Accumulator::default();
+        accumulator.add(&values[..3]);
+        accumulator.add(&values[3..]);
+        let stats = accumulator.finish();
+
+        assert_eq!(stats.min, -1.0);
+        assert_eq!(stats.max, 2.0);
+        assert_eq!(stats.mean, 0.5);
+        assert_eq!(stats.std, 1.25f64.sqrt());
+        assert_eq!(stats.l2_norm, 6f64.sqrt());
+        assert_eq!(stats.zeros, 1.0 / 6.0);
+        assert_eq!(stats.nan_count, 1);
+        assert_eq!(stats.inf_count, 1);
+
+        let mut histogram = Histogram { counts: vec![0; 3] };
+        histogram.add(&values, &stats);
+        assert_eq!(histogram.counts, vec![1, 1, 2]);
+    }
+
+    #[test]
+    fn calculate_std_large_offset() {
+        // The naive sum of squares formula is inaccurate for values far from zero
+        let values: Vec<f32> = (0..1000).map(|i| 1e7 + (i % 3) as f32).collect();
+
+        let mut accumulator = Accumulator::default();
+        accumulator.add(&values);
+        let stats = accumulator.finish();
+
+        let expected = (2.0f64 / 3.0).sqrt();
+        assert!((stats.std - expected).abs() < 1e-3, "std {}", stats.std);
+    }
+}
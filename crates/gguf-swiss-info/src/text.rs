@@ -0,0 +1,541 @@
+use std::io::Write;
+
+use anyhow::Error;
+use gguf_swiss::{MetadataArray, MetadataValue};
+
+use crate::{
+    array::{format_element, ArraySelection},
+    sizes::{format_size, SizeEntry, SizeReport},
+    stats::TensorStats,
+    tree::{self, natural_cmp, TreeEntry},
+    Info, PKG_NAME, PKG_VERSION,
+};
+
+/// Options for text output formats.
+pub struct TextOptions {
+    pub selection: ArraySelection,
+
+    /// Group per-layer tensors into templates.
+    pub tree: bool,
+}
+
+pub fn print_markdown(
+    out: &mut impl Write,
+    info: &Info,
+    options: &TextOptions,
+) -> Result<(), Error> {
+    let header = &info.header;
+    let selection = &options.selection;
+    let card = &info.card;
+
+    // Print information
+    writeln!(out, "# {} - GGUF Model Information", card.name)?;
+    writeln!(out, "Name: {}", card.name)?;
+    writeln!(out, "Author: {}", card.author)?;
+    writeln!(out, "URL: {}", card.url)?;
+    writeln!(out, "Description: {}", card.description)?;
+    writeln!(out, "License: {}", card.license)?;
+    writeln!(out, "Architecture: {}", card.architecture)?;
+    writeln!(out, "GGUF Version: {}", 3)?;
+    writeln!(out, "Metadata KV Count: {}", header.metadata.len())?;
+    writeln!(out, "Tensor Count: {}", header.tensors.len())?;
+
+    writeln!(out, "\n## Metadata")?;
+    for (key, value) in &header.metadata {
+        writeln!(out, "`{}`: {}", key, value)?;
+
+        // Show selected array elements as a list
+        let MetadataValue::Array(array) = value else {
+            continue;
+        };
+        let mut next = 0;
+        for range in selection.ranges(array.len()) {
+            if range.start != next {
+                writeln!(out, "- ...")?;
+            }
+            next = range.end;
+
+            for i in range {
+                writeln!(out, "- `{}`: {}", i, format_element(array, i, selection))?;
+            }
+        }
+        if next != 0 && next != array.len() {
+            writeln!(out, "- ...")?;
+        }
+    }
+
+    writeln!(out, "\n## Tensors")?;
+    let mut tensors: Vec<_> = header.tensors.iter().enumerate().collect();
+    tensors.sort_by(|(_, a), (_, b)| natural_cmp(&a.name, &b.name));
+    if options.tree {
+        print_tree_markdown(out, info)?;
+    } else {
+        for (_, tensor) in &tensors {
+            writeln!(
+                out,
+                "`{}`: `{{type: {:?}, dimensions: {}, offset: {}, size: {}}}`",
+                tensor.name,
+                tensor.tensor_type,
+                tensor.dimensions,
+                tensor.offset,
+                tensor.size()
+            )?;
+        }
+    }
+
+    if let Some(sizes) = &info.sizes {
+        print_sizes_markdown(out, sizes)?;
+    }
+
+    if info.stats.is_some() {
+        writeln!(out, "\n## Tensor Statistics")?;
+        writeln!(
+            out,
+            "| Tensor | Min | Max | Mean | Std | L2 Norm | Zeros | NaN | Inf |"
+        )?;
+        writeln!(
+            out,
+            "| --- | --- | --- | --- | --- | --- | --- | --- | --- |"
+        )?;
+        for (i, tensor) in &tensors {
+            let Some(stats) = info.tensor_stats(*i) else {
+                writeln!(out, "| `{}` | unsupported | | | | | | | |", tensor.name)?;
+                continue;
+            };
+
+            writeln!(
+                out,
+                "| `{}` | {:.6} | {:.6} | {:.6} | {:.6} | {:.6} | {:.2}% | {} | {} |",
+                tensor.name,
+                stats.min,
+                stats.max,
+                stats.mean,
+                stats.std,
+                stats.l2_norm,
+                stats.zeros * 100.0,
+                stats.nan_count,
+                stats.inf_count
+            )?;
+        }
+
+        for (i, tensor) in &tensors {
+            let Some(stats) = info.tensor_stats(*i) else {
+                continue;
+            };
+            if stats.histogram.is_none() {
+                continue;
+            }
+
+            writeln!(out, "\n### `{}`", tensor.name)?;
+            writeln!(out, "```")?;
+            print_histogram(out, stats)?;
+            writeln!(out, "```")?;
+        }
+    }
+
+    // Generation metadata for debugging
+    writeln!(out, "\n## GGUF Swiss")?;
+    writeln!(
+        out,
+        "Model information generated by {} {}.",
+        PKG_NAME, PKG_VERSION
+    )?;
+
+    Ok(())
+}
+
+fn print_tree_markdown(out: &mut impl Write, info: &Info) -> Result<(), Error> {
+    for entry in tree::build(&info.header) {
+        let template = match entry {
+            TreeEntry::Tensor(tensor) => {
+                writeln!(
+                    out,
+                    "`{}`: `{{type: {:?}, dimensions: {}}}`",
+                    tensor.name, tensor.tensor_type, tensor.dimensions
+                )?;
+                continue;
+            }
+            TreeEntry::Template(template) => template,
+        };
+
+        writeln!(
+            out,
+            "`{}`: `{{type: {:?}, dimensions: {}}}`",
+            template.name(),
+            template.tensor_type,
+            template.dimensions
+        )?;
+        for tensor in &template.deviations {
+            writeln!(
+                out,
+                "- deviates: `{}`: `{{type: {:?}, dimensions: {}}}`",
+                tensor.name, tensor.tensor_type, tensor.dimensions
+            )?;
+        }
+        if !template.missing.is_empty() {
+            let missing: Vec<_> = template.missing.iter().map(|l| l.to_string()).collect();
+            writeln!(out, "- missing in layers: {}", missing.join(", "))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_sizes_markdown(out: &mut impl Write, sizes: &SizeReport) -> Result<(), Error> {
+    let percentage = |size: u64| size as f64 / sizes.file_size.max(1) as f64 * 100.0;
+
+    writeln!(out, "\n## Size")?;
+    writeln!(out, "File Size: {}", format_size(sizes.file_size))?;
+    writeln!(
+        out,
+        "Tensor Data Size: {} ({:.2}%)",
+        format_size(sizes.tensor_size),
+        percentage(sizes.tensor_size)
+    )?;
+    writeln!(out, "Parameters: {}", sizes.parameters)?;
+    writeln!(out, "Bits Per Parameter: {:.2}", sizes.bits_per_parameter)?;
+    writeln!(
+        out,
+        "Embedding Size: {} ({:.2}%)",
+        format_size(sizes.embedding_size),
+        percentage(sizes.embedding_size)
+    )?;
+    writeln!(
+        out,
+        "Body Size: {} ({:.2}%)",
+        format_size(sizes.body_size),
+        percentage(sizes.body_size)
+    )?;
+    writeln!(
+        out,
+        "Estimated Memory: {} as-is, {} as F16, {} as F32 (excluding runtime state)",
+        format_size(sizes.memory.native),
+        format_size(sizes.memory.f16),
+        format_size(sizes.memory.f32)
+    )?;
+
+    let mut print_table = |title: &str, entries: &[SizeEntry]| -> Result<(), Error> {
+        writeln!(out, "\n### Size by {}", title)?;
+        writeln!(
+            out,
+            "| {} | Tensors | Parameters | Size | % of File |",
+            title
+        )?;
+        writeln!(out, "| --- | --- | --- | --- | --- |")?;
+        for entry in entries {
+            writeln!(
+                out,
+                "| `{}` | {} | {} | {} | {:.2}% |",
+                entry.name,
+                entry.tensors,
+                entry.parameters,
+                format_size(entry.size),
+                entry.percentage
+            )?;
+        }
+
+        Ok(())
+    };
+    print_table("Type", &sizes.types)?;
+    print_table("Group", &sizes.groups)?;
+
+    Ok(())
+}
+
+/// Print plain tab-separated text, for simple line-based parsing.
+///
+/// Every line starts with the kind of record, `model`, `metadata`, `element`, or `tensor`.
+/// Selected array elements are printed as `element` records, with their index.
+/// If requested, tensor statistics are printed as `stats` and `histogram` records, and size
+/// breakdowns as `size`, `size_type` and `size_group` records.
+/// With the tree option, layer templates are printed as `template`, `deviation`, and `missing`
+/// records.
+/// Backslashes, tabs and newlines in model card fields are escaped as `\\`, `\t` and `\n`.
+pub fn print_plain(out: &mut impl Write, info: &Info, options: &TextOptions) -> Result<(), Error> {
+    let header = &info.header;
+    let selection = &options.selection;
+    let card = &info.card;
+
+    let fields = [
+        ("name", &card.name),
+        ("author", &card.author),
+        ("url", &card.url),
+        ("description", &card.description),
+        ("license", &card.license),
+        ("architecture", &card.architecture),
+    ];
+    for (field, value) in fields {
+        writeln!(out, "model\t{}\t{}", field, escape_plain(value))?;
+    }
+
+    for (key, value) in &header.metadata {
+        let ty = value.type_name();
+        writeln!(out, "metadata\t{}\t{}\t{}", key, ty, value)?;
+
+        let MetadataValue::Array(array) = value else {
+            continue;
+        };
+        for i in selection.ranges(array.len()).into_iter().flatten() {
+            let element = format_element(array, i, selection);
+            writeln!(out, "element\t{}\t{}\t{}", key, i, element)?;
+        }
+    }
+
+    for (i, tensor) in header.tensors.iter().enumerate() {
+        writeln!(
+            out,
+            "tensor\t{}\t{:?}\t{}\t{}\t{}",
+            tensor.name,
+            tensor.tensor_type,
+            tensor.dimensions,
+            tensor.offset,
+            tensor.size()
+        )?;
+
+        let Some(stats) = info.tensor_stats(i) else {
+            continue;
+        };
+        writeln!(
+            out,
+            "stats\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            tensor.name,
+            stats.min,
+            stats.max,
+            stats.mean,
+            stats.std,
+            stats.l2_norm,
+            stats.zeros,
+            stats.nan_count,
+            stats.inf_count
+        )?;
+
+        let Some(histogram) = &stats.histogram else {
+            continue;
+        };
+        for (bin, count) in histogram.counts.iter().enumerate() {
+            let (start, end) = histogram.bin_range(stats, bin);
+            writeln!(
+                out,
+                "histogram\t{}\t{}\t{}\t{}",
+                tensor.name, start, end, count
+            )?;
+        }
+    }
+
+    if options.tree {
+        print_tree_plain(out, info)?;
+    }
+
+    if let Some(sizes) = &info.sizes {
+        writeln!(out, "size\tfile_size\t{}", sizes.file_size)?;
+        writeln!(out, "size\ttensor_size\t{}", sizes.tensor_size)?;
+        writeln!(out, "size\tparameters\t{}", sizes.parameters)?;
+        writeln!(
+            out,
+            "size\tbits_per_parameter\t{}",
+            sizes.bits_per_parameter
+        )?;
+        writeln!(out, "size\tembedding_size\t{}", sizes.embedding_size)?;
+        writeln!(out, "size\tbody_size\t{}", sizes.body_size)?;
+        writeln!(out, "size\tmemory_native\t{}", sizes.memory.native)?;
+        writeln!(out, "size\tmemory_f16\t{}", sizes.memory.f16)?;
+        writeln!(out, "size\tmemory_f32\t{}", sizes.memory.f32)?;
+
+        let records = [("size_type", &sizes.types), ("size_group", &sizes.groups)];
+        for (record, entries) in records {
+            for entry in entries {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    record,
+                    entry.name,
+                    entry.tensors,
+                    entry.parameters,
+                    entry.size,
+                    entry.percentage
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_tree_plain(out: &mut impl Write, info: &Info) -> Result<(), Error> {
+    for entry in tree::build(&info.header) {
+        let TreeEntry::Template(template) = entry else {
+            continue;
+        };
+
+        let name = template.name();
+        writeln!(
+            out,
+            "template\t{}\t{:?}\t{}\t{}",
+            name,
+            template.tensor_type,
+            template.dimensions,
+            template.layers.len()
+        )?;
+        for tensor in &template.deviations {
+            writeln!(
+                out,
+                "deviation\t{}\t{}\t{:?}\t{}",
+                name, tensor.name, tensor.tensor_type, tensor.dimensions
+            )?;
+        }
+        for layer in &template.missing {
+            writeln!(out, "missing\t{}\t{}", name, layer)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Escape a value so it fits in a single tab-separated field.
+fn escape_plain(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '\\' => text.push_str("\\\\"),
+            '\t' => text.push_str("\\t"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            c => text.push(c),
+        }
+    }
+
+    text
+}
+
+/// Print a single value without quotes or escapes, for use in scripts.
+///
+/// Array elements are printed one per line.
+pub fn print_raw(out: &mut impl Write, value: &MetadataValue) -> Result<(), Error> {
+    match value {
+        MetadataValue::String(value) => out.write_all(value)?,
+        MetadataValue::Array(MetadataArray::String(values)) => {
+            for (i, value) in values.iter().enumerate() {
+                if i != 0 {
+                    writeln!(out)?;
+                }
+                out.write_all(value)?;
+            }
+        }
+        MetadataValue::Array(array) => {
+            let selection = ArraySelection {
+                full: true,
+                ..Default::default()
+            };
+            let elements: Vec<_> = (0..array.len())
+                .map(|i| format_element(array, i, &selection))
+                .collect();
+            write!(out, "{}", elements.join("\n"))?;
+        }
+        value => write!(out, "{}", value)?,
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn print_histogram(out: &mut impl Write, stats: &TensorStats) -> Result<(), Error> {
+    let Some(histogram) = &stats.histogram else {
+        return Ok(());
+    };
+
+    let largest = histogram.counts.iter().copied().max().unwrap_or(0).max(1);
+    for (bin, count) in histogram.counts.iter().enumerate() {
+        let (start, end) = histogram.bin_range(stats, bin);
+        let bar = "#".repeat((count * 40 / largest) as usize);
+        writeln!(
+            out,
+            "{:>12.6} .. {:>12.6} | {:<40} {}",
+            start, end, bar, count
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use gguf_swiss::{
+        Header, MetadataArray, MetadataValue, TensorDimensions, TensorInfo, TensorType,
+    };
+
+    use crate::{
+        array::ArraySelection,
+        report::ModelCard,
+        text::{print_markdown, print_plain, TextOptions},
+        Info,
+    };
+
+    /// Build a small model, with a description that needs escaping.
+    pub(crate) fn test_info() -> Info {
+        let string = |value: &str| MetadataValue::String(value.as_bytes().to_vec());
+        let header = Header {
+            metadata: vec![
+                ("general.architecture".to_string(), string("llama")),
+                ("general.name".to_string(), string("Test")),
+                ("general.description".to_string(), string("a\tb\nc")),
+                (
+                    "tokens".to_string(),
+                    MetadataValue::Array(MetadataArray::UInt64(vec![1, 2, 3])),
+                ),
+            ],
+            tensors: vec![TensorInfo {
+                name: "output.weight".to_string(),
+                tensor_type: TensorType::F32,
+                dimensions: TensorDimensions([4, 2, 0, 0]),
+                offset: 0,
+            }],
+        };
+
+        Info {
+            card: ModelCard::from_header(&header).unwrap(),
+            header,
+            stats: None,
+            sizes: None,
+        }
+    }
+
+    fn options() -> TextOptions {
+        TextOptions {
+            selection: ArraySelection {
+                head: Some(1),
+                ..Default::default()
+            },
+            tree: false,
+        }
+    }
+
+    #[test]
+    fn markdown_output() {
+        let mut out = Vec::new();
+        print_markdown(&mut out, &test_info(), &options()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with("# Test - GGUF Model Information\n"));
+        assert!(text.contains("Architecture: llama\n"));
+        assert!(text.contains("`tokens`: [UInt64; 3]\n- `0`: 1\n- ...\n"));
+        assert!(text
+            .contains("`output.weight`: `{type: F32, dimensions: [4, 2], offset: 0, size: 32}`\n"));
+    }
+
+    #[test]
+    fn plain_output() {
+        let mut out = Vec::new();
+        print_plain(&mut out, &test_info(), &options()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+
+        assert_eq!(lines[0], "model\tname\tTest");
+        assert_eq!(lines[3], "model\tdescription\ta\\tb\\nc");
+        assert_eq!(lines[5], "model\tarchitecture\tllama");
+        assert!(lines.contains(&"metadata\ttokens\tArray<UInt64>\t[UInt64; 3]"));
+        assert!(lines.contains(&"element\ttokens\t0\t1"));
+        assert!(lines.contains(&"tensor\toutput.weight\tF32\t[4, 2]\t0\t32"));
+        assert!(lines.iter().all(|line| line.split('\t').count() > 2));
+    }
+}
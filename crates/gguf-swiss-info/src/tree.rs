@@ -0,0 +1,218 @@
+use std::cmp::Ordering;
+
+use gguf_swiss::{Header, TensorDimensions, TensorInfo, TensorType};
+
+/// Tensors grouped by layer, with repeated per-layer tensors collapsed into templates.
+pub enum TreeEntry<'a> {
+    /// A tensor that isn't part of a layer.
+    Tensor(&'a TensorInfo),
+
+    /// A tensor repeated in every layer, as `blk.N.{suffix}`.
+    Template(LayerTemplate<'a>),
+}
+
+pub struct LayerTemplate<'a> {
+    pub suffix: &'a str,
+
+    /// Layers this tensor is present in, sorted.
+    pub layers: Vec<u64>,
+
+    /// The type and dimensions shared by most layers.
+    pub tensor_type: TensorType,
+    pub dimensions: TensorDimensions,
+
+    /// Tensors with a type or dimensions different from the template.
+    pub deviations: Vec<&'a TensorInfo>,
+
+    /// Layers other tensors are present in, that this tensor is missing from.
+    pub missing: Vec<u64>,
+}
+
+impl LayerTemplate<'_> {
+    pub fn name(&self) -> String {
+        format!("blk.{}.{}", format_layers(&self.layers), self.suffix)
+    }
+}
+
+impl TreeEntry<'_> {
+    fn sort_name(&self) -> String {
+        match self {
+            TreeEntry::Tensor(tensor) => tensor.name.clone(),
+            TreeEntry::Template(template) => format!("blk.{}", template.suffix),
+        }
+    }
+}
+
+/// Build the tensor tree, sorted naturally by name.
+pub fn build(header: &Header) -> Vec<TreeEntry<'_>> {
+    let mut entries = Vec::new();
+    let mut layered: Vec<(&str, Vec<(u64, &TensorInfo)>)> = Vec::new();
+    let mut all_layers = Vec::new();
+
+    for tensor in &header.tensors {
+        let Some((layer, suffix)) = split_layer(&tensor.name) else {
+            entries.push(TreeEntry::Tensor(tensor));
+            continue;
+        };
+
+        if !all_layers.contains(&layer) {
+            all_layers.push(layer);
+        }
+        match layered.iter_mut().find(|(s, _)| *s == suffix) {
+            Some((_, tensors)) => tensors.push((layer, tensor)),
+            None => layered.push((suffix, vec![(layer, tensor)])),
+        }
+    }
+
+    all_layers.sort();
+
+    for (suffix, mut tensors) in layered {
+        tensors.sort_by_key(|(layer, _)| *layer);
+
+        // The most common type and dimensions are the template
+        let mut variants: Vec<((TensorType, TensorDimensions), usize)> = Vec::new();
+        for (_, tensor) in &tensors {
+            let key = (tensor.tensor_type, tensor.dimensions);
+            match variants.iter_mut().find(|(v, _)| *v == key) {
+                Some((_, count)) => *count += 1,
+                None => variants.push((key, 1)),
+            }
+        }
+        let ((tensor_type, dimensions), _) = variants
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .expect("at least one tensor per suffix");
+
+        let layers: Vec<u64> = tensors.iter().map(|(layer, _)| *layer).collect();
+        let deviations = tensors
+            .iter()
+            .filter(|(_, t)| t.tensor_type != tensor_type || t.dimensions != dimensions)
+            .map(|(_, t)| *t)
+            .collect();
+        let missing = all_layers
+            .iter()
+            .copied()
+            .filter(|l| !layers.contains(l))
+            .collect();
+
+        entries.push(TreeEntry::Template(LayerTemplate {
+            suffix,
+            layers,
+            tensor_type,
+            dimensions,
+            deviations,
+            missing,
+        }));
+    }
+
+    entries.sort_by(|a, b| natural_cmp(&a.sort_name(), &b.sort_name()));
+    entries
+}
+
+/// Split a tensor name in the format `blk.N.suffix` into the layer index and suffix.
+fn split_layer(name: &str) -> Option<(u64, &str)> {
+    let rest = name.strip_prefix("blk.")?;
+    let (layer, suffix) = rest.split_once('.')?;
+    let layer = layer.parse().ok()?;
+
+    Some((layer, suffix))
+}
+
+/// Format sorted layer indices as exclusive ranges, for example `[0..7, 8..24]`.
+pub fn format_layers(layers: &[u64]) -> String {
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+
+    for layer in layers {
+        match ranges.last_mut() {
+            Some((_, end)) if *end == *layer => *end += 1,
+            _ => ranges.push((*layer, layer + 1)),
+        }
+    }
+
+    let ranges: Vec<_> = ranges
+        .iter()
+        .map(|(start, end)| format!("{}..{}", start, end))
+        .collect();
+    format!("[{}]", ranges.join(", "))
+}
+
+/// Compare strings, treating runs of digits as numbers, so `blk.2` sorts before `blk.10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.as_bytes();
+    let mut b = b.as_bytes();
+
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let (na, ra) = split_digits(a);
+                let (nb, rb) = split_digits(b);
+
+                // Compare numerically, ignoring leading zeros, and by length as a tie-breaker
+                let ta = trim_zeros(na);
+                let tb = trim_zeros(nb);
+                let ordering = ta
+                    .len()
+                    .cmp(&tb.len())
+                    .then_with(|| ta.cmp(tb))
+                    .then_with(|| na.len().cmp(&nb.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+
+                a = ra;
+                b = rb;
+            }
+            (Some(ca), Some(cb)) => {
+                if ca != cb {
+                    return ca.cmp(cb);
+                }
+
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
+fn split_digits(value: &[u8]) -> (&[u8], &[u8]) {
+    let end = value
+        .iter()
+        .position(|c| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    value.split_at(end)
+}
+
+fn trim_zeros(value: &[u8]) -> &[u8] {
+    let start = value.iter().position(|c| *c != b'0').unwrap_or(value.len());
+    &value[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::tree::{format_layers, natural_cmp};
+
+    #[test]
+    fn natural_sort_order() {
+        let mut names = vec!["blk.10.a", "blk.2.b", "blk.2.a", "output", "blk.1.a"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["blk.1.a", "blk.2.a", "blk.2.b", "blk.10.a", "output"]
+        );
+
+        assert_eq!(natural_cmp("a02", "a2"), Ordering::Greater);
+        assert_eq!(natural_cmp("a2", "a2"), Ordering::Equal);
+    }
+
+    #[test]
+    fn format_layer_ranges() {
+        assert_eq!(format_layers(&[0, 1, 2, 3]), "[0..4]");
+        assert_eq!(format_layers(&[0, 1, 3]), "[0..2, 3..4]");
+    }
+}
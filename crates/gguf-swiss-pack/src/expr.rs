@@ -0,0 +1,230 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::{bail, Context, Error};
+
+/// Result of evaluating an expression.
+///
+/// Arithmetic on two integers stays integer, with `/` truncating, anything involving a float
+/// results in a float.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(value) => value as f64,
+            Number::Float(value) => value,
+        }
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Int(value) => write!(f, "{}", value),
+            Number::Float(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Evaluate a simple arithmetic expression, like `n_embd * 4 / 3`.
+///
+/// Supports `+`, `-`, `*`, `/`, `%`, parentheses, and variables looked up by name.
+pub fn evaluate(
+    expression: &str,
+    lookup: &mut dyn FnMut(&str) -> Result<Number, Error>,
+) -> Result<Number, Error> {
+    let mut parser = Parser {
+        rest: expression,
+        lookup,
+    };
+
+    let value = parser.expression()?;
+    if !parser.rest.trim().is_empty() {
+        bail!(
+            "unexpected {:?} in expression {:?}",
+            parser.rest,
+            expression
+        );
+    }
+
+    Ok(value)
+}
+
+struct Parser<'a, 'b> {
+    rest: &'a str,
+    lookup: &'b mut dyn FnMut(&str) -> Result<Number, Error>,
+}
+
+impl<'a> Parser<'a, '_> {
+    fn expression(&mut self) -> Result<Number, Error> {
+        let mut value = self.term()?;
+
+        while let Some(op) = self.take_any(&['+', '-']) {
+            let rhs = self.term()?;
+            value = apply(op, value, rhs)?;
+        }
+
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<Number, Error> {
+        let mut value = self.unary()?;
+
+        while let Some(op) = self.take_any(&['*', '/', '%']) {
+            let rhs = self.unary()?;
+            value = apply(op, value, rhs)?;
+        }
+
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<Number, Error> {
+        if self.take_any(&['-']).is_some() {
+            return apply('-', Number::Int(0), self.unary()?);
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Number, Error> {
+        self.rest = self.rest.trim_start();
+
+        if self.take_any(&['(']).is_some() {
+            let value = self.expression()?;
+            if self.take_any(&[')']).is_none() {
+                bail!("expected \")\"");
+            }
+            return Ok(value);
+        }
+
+        let first = self
+            .rest
+            .chars()
+            .next()
+            .context("unexpected end of expression")?;
+
+        if first.is_ascii_digit() {
+            let (token, rest) = self.rest.split_at(number_len(self.rest));
+            self.rest = rest;
+
+            let value = if token.contains(['.', 'e', 'E']) {
+                Number::Float(token.parse().context("invalid number")?)
+            } else {
+                Number::Int(token.parse().context("invalid number")?)
+            };
+            return Ok(value);
+        }
+
+        if first.is_ascii_alphabetic() || first == '_' {
+            let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+            return (self.lookup)(name);
+        }
+
+        bail!("unexpected {:?} in expression", first)
+    }
+
+    fn take_any(&mut self, ops: &[char]) -> Option<char> {
+        self.rest = self.rest.trim_start();
+
+        let op = self.rest.chars().next().filter(|c| ops.contains(c))?;
+        self.rest = &self.rest[1..];
+        Some(op)
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let end = self.rest.find(|c| !f(c)).unwrap_or(self.rest.len());
+        let (token, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        token
+    }
+}
+
+/// Length of the number at the start of text, with an optional exponent like `1e-5`.
+fn number_len(text: &str) -> usize {
+    let digits = |start: usize| {
+        let rest = &text[start..];
+        start
+            + rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len())
+    };
+
+    let end = digits(0);
+    let bytes = text.as_bytes();
+    if !matches!(bytes.get(end), Some(b'e' | b'E')) {
+        return end;
+    }
+
+    let mut exponent = end + 1;
+    if matches!(bytes.get(exponent), Some(b'+' | b'-')) {
+        exponent += 1;
+    }
+    if !bytes.get(exponent).is_some_and(u8::is_ascii_digit) {
+        return end;
+    }
+
+    digits(exponent)
+}
+
+fn apply(op: char, lhs: Number, rhs: Number) -> Result<Number, Error> {
+    if let (Number::Int(lhs), Number::Int(rhs)) = (lhs, rhs) {
+        let value = match op {
+            '+' => lhs.checked_add(rhs),
+            '-' => lhs.checked_sub(rhs),
+            '*' => lhs.checked_mul(rhs),
+            '/' => lhs.checked_div(rhs),
+            _ => lhs.checked_rem(rhs),
+        };
+        let value = value.with_context(|| format!("invalid arithmetic {} {} {}", lhs, op, rhs))?;
+        return Ok(Number::Int(value));
+    }
+
+    let (lhs, rhs) = (lhs.as_f64(), rhs.as_f64());
+    let value = match op {
+        '+' => lhs + rhs,
+        '-' => lhs - rhs,
+        '*' => lhs * rhs,
+        '/' => lhs / rhs,
+        _ => lhs % rhs,
+    };
+    Ok(Number::Float(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::bail;
+
+    use crate::expr::{evaluate, Number};
+
+    #[test]
+    fn evaluate_expressions() {
+        let mut lookup = |name: &str| match name {
+            "n_embd" => Ok(Number::Int(2048)),
+            "eps" => Ok(Number::Float(0.5)),
+            _ => bail!("unknown variable {:?}", name),
+        };
+
+        let mut eval = |expression| evaluate(expression, &mut lookup);
+        assert_eq!(eval("n_embd * 3").unwrap(), Number::Int(6144));
+        assert_eq!(eval(" (n_embd + 2) / 10 % 7").unwrap(), Number::Int(2));
+        assert_eq!(eval("-2 * -(3 - 1)").unwrap(), Number::Int(4));
+        assert_eq!(eval("eps * 3").unwrap(), Number::Float(1.5));
+        assert_eq!(eval("1.5").unwrap(), Number::Float(1.5));
+        assert_eq!(eval("1e-5").unwrap(), Number::Float(1e-5));
+        assert_eq!(eval("2.5E+2*2").unwrap(), Number::Float(500.0));
+        assert_eq!(eval("3e2").unwrap(), Number::Float(300.0));
+
+        assert!(eval("n_layer").is_err());
+        assert!(eval("1 / 0").is_err());
+        assert!(eval("(1 + 2").is_err());
+        assert!(eval("1 2").is_err());
+        assert!(eval("2e").is_err());
+        assert!(eval("2e-").is_err());
+        assert!(eval("1e5.5").is_err());
+        assert!(eval("").is_err());
+    }
+}
@@ -1,4 +1,8 @@
+mod expr;
 mod manifest;
+mod ops;
+mod pattern;
+mod pipeline;
 mod safetensors;
 mod tasks;
 
@@ -30,11 +34,17 @@ fn main() -> Result<(), Error> {
 
     // Load the manifest that describes how to perform conversion
     println!("loading manifest");
-    let manifest =
-        manifest::read_manifest(&manifest_path).context("failed to load packaging manifest")?;
+    let vars: Result<Vec<_>, _> = args.var.iter().map(|var| parse_var(var)).collect();
+    let manifest = manifest::read_manifest(&manifest_path, &vars?)
+        .context("failed to load packaging manifest")?;
+
+    let jobs = match args.jobs {
+        Some(jobs) => jobs,
+        None => std::thread::available_parallelism().map_or(1, |v| v.get()),
+    };
 
     // Perform conversion
-    convert_from_manifest(&manifest, &source_path, &output).context("failed to convert")?;
+    convert_from_manifest(&manifest, &source_path, jobs, &output).context("failed to convert")?;
 
     Ok(())
 }
@@ -55,11 +65,28 @@ struct Args {
 
     /// Path to the output file.
     output: String,
+
+    /// Amount of threads to convert tensors with, defaults to the amount of CPU cores.
+    #[arg(long, short)]
+    jobs: Option<usize>,
+
+    /// Set a manifest variable, as `NAME=EXPRESSION`, overriding the manifest's `[vars]`.
+    #[arg(long, value_name = "NAME=EXPRESSION")]
+    var: Vec<String>,
+}
+
+fn parse_var(value: &str) -> Result<(String, String), Error> {
+    let (name, expression) = value
+        .split_once('=')
+        .with_context(|| format!("expected \"NAME=EXPRESSION\", got {:?}", value))?;
+
+    Ok((name.trim().to_string(), expression.to_string()))
 }
 
 fn convert_from_manifest(
     manifest: &Manifest,
     source_path: &Path,
+    jobs: usize,
     output: &PathBuf,
 ) -> Result<(), Error> {
     // Load and process tasks
@@ -71,7 +98,7 @@ fn convert_from_manifest(
     write_header(&mut output, metadata, tensors)?;
 
     // Perform tensor conversion
-    tasks::write_tensors(&mut tasks, source_path, &mut output)?;
+    tasks::write_tensors(&mut tasks, jobs, &mut output)?;
 
     Ok(())
 }
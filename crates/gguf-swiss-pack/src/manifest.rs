@@ -4,16 +4,34 @@ use anyhow::{bail, Context, Error};
 use serde::Deserialize;
 use toml::{Table, Value};
 
-pub fn read_manifest(path: &Path) -> Result<Manifest, Error> {
+use crate::expr::{self, Number};
+
+/// Read a manifest, substituting variables.
+///
+/// `overrides` are expressions for variables, taking precedence over the manifest's `[vars]`.
+pub fn read_manifest(path: &Path, overrides: &[(String, String)]) -> Result<Manifest, Error> {
     // Try opening the manifest
     let manifest_str = std::fs::read_to_string(path).context("failed to open")?;
-    let manifest_value: Value = toml::from_str(&manifest_str).context("failed to parse")?;
+    let mut manifest_value: Value = toml::from_str(&manifest_str).context("failed to parse")?;
 
     // Validate matching manifest version
     if manifest_value.get("manifest_version") != Some(&Value::from(0)) {
         bail!("not a manifest or unsupported version");
     }
 
+    // Substitute variables everywhere else in the manifest
+    let table = manifest_value.as_table_mut().context("not a table")?;
+    let mut vars = match table.remove("vars") {
+        Some(Value::Table(vars)) => vars,
+        Some(_) => bail!("\"vars\" not a table"),
+        None => Table::new(),
+    };
+    for (name, expression) in overrides {
+        vars.insert(name.clone(), Value::String(expression.clone()));
+    }
+    let mut vars = Vars::new(vars);
+    substitute(&mut manifest_value, &mut vars).context("failed to substitute variables")?;
+
     let value: Manifest = manifest_value.try_into().context("failed to decode")?;
 
     Ok(value)
@@ -23,3 +41,207 @@ pub fn read_manifest(path: &Path) -> Result<Manifest, Error> {
 pub struct Manifest {
     pub tasks: HashMap<String, Table>,
 }
+
+/// Manifest variables, evaluated when first used.
+///
+/// Variables are numbers, or strings with an expression that can use other variables.
+struct Vars {
+    raw: Table,
+    values: HashMap<String, Number>,
+
+    /// Variables currently being evaluated, to detect cycles.
+    evaluating: Vec<String>,
+}
+
+impl Vars {
+    fn new(raw: Table) -> Self {
+        Self {
+            raw,
+            values: HashMap::new(),
+            evaluating: Vec::new(),
+        }
+    }
+
+    fn get(&mut self, name: &str) -> Result<Number, Error> {
+        if let Some(value) = self.values.get(name) {
+            return Ok(*value);
+        }
+
+        if self.evaluating.iter().any(|n| n == name) {
+            bail!("variable {:?} depends on itself", name);
+        }
+
+        let value = match self.raw.get(name) {
+            Some(Value::Integer(value)) => Number::Int(*value),
+            Some(Value::Float(value)) => Number::Float(*value),
+            Some(Value::String(expression)) => {
+                let expression = expression.clone();
+
+                self.evaluating.push(name.to_string());
+                let value = self.evaluate(&expression);
+                self.evaluating.pop();
+
+                value.with_context(|| format!("failed to evaluate variable {:?}", name))?
+            }
+            Some(_) => bail!("variable {:?} not a number or expression", name),
+            None => bail!("unknown variable {:?}", name),
+        };
+
+        self.values.insert(name.to_string(), value);
+        Ok(value)
+    }
+
+    fn evaluate(&mut self, expression: &str) -> Result<Number, Error> {
+        expr::evaluate(expression, &mut |name| self.get(name))
+    }
+}
+
+/// Substitute `${expression}` in all strings and keys, `$${` is a literal `${`.
+///
+/// A string that is only a single expression is replaced by the resulting number.
+/// Keys of the same table that become the same are an error.
+fn substitute(value: &mut Value, vars: &mut Vars) -> Result<(), Error> {
+    match value {
+        Value::String(text) => {
+            let whole = text
+                .strip_prefix("${")
+                .and_then(|rest| rest.strip_suffix('}'))
+                .filter(|expression| !expression.contains(['{', '}']));
+
+            *value = match whole {
+                Some(expression) => match vars.evaluate(expression)? {
+                    Number::Int(value) => Value::Integer(value),
+                    Number::Float(value) => Value::Float(value),
+                },
+                None => Value::String(interpolate(text, vars)?),
+            };
+        }
+        Value::Array(values) => {
+            for value in values {
+                substitute(value, vars)?;
+            }
+        }
+        Value::Table(table) => {
+            let entries = std::mem::take(table);
+            for (key, mut value) in entries {
+                substitute(&mut value, vars)?;
+
+                let substituted = interpolate(&key, vars)?;
+                if table.contains_key(&substituted) {
+                    bail!(
+                        "key {:?} becomes {:?}, which already exists",
+                        key,
+                        substituted
+                    );
+                }
+                table.insert(substituted, value);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Replace every `${expression}` in text with the resulting number, and `$${` with `${`.
+fn interpolate(text: &str, vars: &mut Vars) -> Result<String, Error> {
+    let mut result = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let from_dollar = &rest[start..];
+
+        if let Some(after) = from_dollar.strip_prefix("$${") {
+            result.push_str("${");
+            rest = after;
+        } else if let Some(expression) = from_dollar.strip_prefix("${") {
+            let end = expression
+                .find('}')
+                .with_context(|| format!("unclosed expression in {:?}", text))?;
+
+            let value = vars.evaluate(&expression[..end])?;
+            result.push_str(&value.to_string());
+            rest = &expression[end + 1..];
+        } else {
+            result.push('$');
+            rest = &from_dollar[1..];
+        }
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::{Table, Value};
+
+    use crate::manifest::{substitute, Vars};
+
+    #[test]
+    fn substitute_vars() {
+        let vars: Table = toml::from_str("n_embd = 2048\nn_layer = \"n_embd / 85\"").unwrap();
+        let mut vars = Vars::new(vars);
+
+        let mut value: Value = toml::from_str(
+            r#"
+            dimensions = ["${n_embd}", "${n_embd * 3}"]
+            "$0..${n_layer}" = { name = "blk.${n_layer - 1}" }
+            "#,
+        )
+        .unwrap();
+        substitute(&mut value, &mut vars).unwrap();
+
+        let expected: Value = toml::from_str(
+            r#"
+            dimensions = [2048, 6144]
+            "$0..24" = { name = "blk.23" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn escape_and_duplicates() {
+        let vars: Table = toml::from_str(
+            "n = 2
+m = 2",
+        )
+        .unwrap();
+        let mut vars = Vars::new(vars);
+
+        let mut value: Value = toml::from_str(
+            r#"
+            template = "{% set x = `$${name}` %}$${n}, ${n * 2}$"
+            whole = "$${n}"
+            "#,
+        )
+        .unwrap();
+        substitute(&mut value, &mut vars).unwrap();
+        assert_eq!(
+            value["template"].as_str(),
+            Some("{% set x = `${name}` %}${n}, 4$")
+        );
+        assert_eq!(value["whole"].as_str(), Some("${n}"));
+
+        let mut value: Value = toml::from_str(
+            r#"
+            "blk.${n}" = 1
+            "blk.${m}" = 2
+            "#,
+        )
+        .unwrap();
+        let error = substitute(&mut value, &mut vars).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("becomes \"blk.2\", which already exists"));
+    }
+
+    #[test]
+    fn cyclic_vars() {
+        let vars: Table = toml::from_str("a = \"b + 1\"\nb = \"a * 2\"").unwrap();
+        assert!(Vars::new(vars).get("a").is_err());
+    }
+}
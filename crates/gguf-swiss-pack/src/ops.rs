@@ -0,0 +1,424 @@
+//! Transformations applied to source tensors before they're converted.
+//!
+//! Shapes and axes are width-last, in the same order as the source safetensors.
+
+use anyhow::{bail, Context, Error};
+use serde::Deserialize;
+
+/// Tensor values in row-major order.
+pub struct Tensor {
+    pub shape: Vec<u64>,
+    pub values: Vec<f32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TensorOp {
+    /// Permute the axes, by default swapping the last two.
+    Transpose {
+        #[serde(default)]
+        axes: Option<Vec<usize>>,
+    },
+
+    /// Change the shape, keeping the amount of values.
+    Reshape { shape: Vec<u64> },
+
+    /// Remove an axis of size 1.
+    Squeeze { axis: usize },
+
+    /// Insert an axis of size 1.
+    Unsqueeze { axis: usize },
+
+    /// Append other source tensors along an axis.
+    Concat { axis: usize, sources: Vec<String> },
+
+    /// Keep only the range `start..end` along an axis, which can't be empty.
+    Slice { axis: usize, start: u64, end: u64 },
+
+    /// Multiply all values by a factor.
+    Scale { factor: f32 },
+
+    /// Negate all values.
+    Neg,
+
+    /// Apply `e^x` to all values.
+    Exp,
+}
+
+impl TensorOp {
+    fn name(&self) -> &'static str {
+        match self {
+            TensorOp::Transpose { .. } => "transpose",
+            TensorOp::Reshape { .. } => "reshape",
+            TensorOp::Squeeze { .. } => "squeeze",
+            TensorOp::Unsqueeze { .. } => "unsqueeze",
+            TensorOp::Concat { .. } => "concat",
+            TensorOp::Slice { .. } => "slice",
+            TensorOp::Scale { .. } => "scale",
+            TensorOp::Neg => "neg",
+            TensorOp::Exp => "exp",
+        }
+    }
+
+    /// Map the names of source tensors this op reads, for expansion macros and mapping rules.
+    pub fn map_sources(
+        &self,
+        mut f: impl FnMut(&str) -> Result<String, Error>,
+    ) -> Result<Self, Error> {
+        let op = match self {
+            TensorOp::Concat { axis, sources } => TensorOp::Concat {
+                axis: *axis,
+                sources: sources.iter().map(|s| f(s)).collect::<Result<_, _>>()?,
+            },
+            op => op.clone(),
+        };
+
+        Ok(op)
+    }
+
+    /// Names of additional source tensors this op reads.
+    pub fn sources(&self) -> &[String] {
+        match self {
+            TensorOp::Concat { sources, .. } => sources,
+            _ => &[],
+        }
+    }
+
+    /// Get the shape of the result of this op, validating it can be applied.
+    fn output_shape(&self, shape: &[u64], source_shapes: &[Vec<u64>]) -> Result<Vec<u64>, Error> {
+        let mut shape = shape.to_vec();
+
+        match self {
+            TensorOp::Transpose { axes } => {
+                let axes = transpose_axes(axes.as_deref(), shape.len())?;
+                shape = axes.iter().map(|axis| shape[*axis]).collect();
+            }
+            TensorOp::Reshape { shape: new_shape } => {
+                let total: u64 = shape.iter().product();
+                let new_total: u64 = new_shape.iter().product();
+                if total != new_total {
+                    bail!("can't reshape {:?} to {:?}", shape, new_shape);
+                }
+                shape = new_shape.clone();
+            }
+            TensorOp::Squeeze { axis } => {
+                if shape.get(*axis) != Some(&1) {
+                    bail!("axis {} of {:?} isn't of size 1", axis, shape);
+                }
+                shape.remove(*axis);
+            }
+            TensorOp::Unsqueeze { axis } => {
+                if *axis > shape.len() {
+                    bail!("axis {} out of range for {:?}", axis, shape);
+                }
+                shape.insert(*axis, 1);
+            }
+            TensorOp::Concat { axis, .. } => {
+                check_axis(*axis, &shape)?;
+
+                for other in source_shapes {
+                    let matches = other.len() == shape.len()
+                        && (0..shape.len()).all(|i| i == *axis || other[i] == shape[i]);
+                    if !matches {
+                        bail!(
+                            "can't concatenate {:?} and {:?} along axis {}",
+                            shape,
+                            other,
+                            axis
+                        );
+                    }
+
+                    shape[*axis] += other[*axis];
+                }
+            }
+            TensorOp::Slice { axis, start, end } => {
+                check_axis(*axis, &shape)?;
+                if start >= end || *end > shape[*axis] {
+                    bail!(
+                        "slice {}..{} empty or out of range for axis {} of {:?}",
+                        start,
+                        end,
+                        axis,
+                        shape
+                    );
+                }
+                shape[*axis] = end - start;
+            }
+            TensorOp::Scale { .. } | TensorOp::Neg | TensorOp::Exp => {}
+        }
+
+        Ok(shape)
+    }
+
+    fn apply(&self, tensor: Tensor, sources: Vec<Tensor>) -> Result<Tensor, Error> {
+        let source_shapes: Vec<_> = sources.iter().map(|s| s.shape.clone()).collect();
+        let shape = self.output_shape(&tensor.shape, &source_shapes)?;
+
+        let values = match self {
+            TensorOp::Transpose { axes } => {
+                let axes = transpose_axes(axes.as_deref(), tensor.shape.len())?;
+                transpose(&tensor, &axes, &shape)
+            }
+            TensorOp::Reshape { .. } | TensorOp::Squeeze { .. } | TensorOp::Unsqueeze { .. } => {
+                tensor.values
+            }
+            TensorOp::Concat { axis, .. } => {
+                let tensors: Vec<_> = [tensor].into_iter().chain(sources).collect();
+                concat(&tensors, *axis)
+            }
+            TensorOp::Slice { axis, start, end } => slice(&tensor, *axis, *start, *end),
+            TensorOp::Scale { factor } => map(tensor.values, |v| v * factor),
+            TensorOp::Neg => map(tensor.values, |v| -v),
+            TensorOp::Exp => map(tensor.values, f32::exp),
+        };
+
+        Ok(Tensor { shape, values })
+    }
+}
+
+/// Get the shape of the result of a list of ops.
+///
+/// `source_shape` gets the shape of additional source tensors the ops read.
+pub fn output_shape(
+    ops: &[TensorOp],
+    shape: &[u64],
+    mut source_shape: impl FnMut(&str) -> Result<Vec<u64>, Error>,
+) -> Result<Vec<u64>, Error> {
+    let mut shape = shape.to_vec();
+
+    for (i, op) in ops.iter().enumerate() {
+        let source_shapes: Result<Vec<_>, _> =
+            op.sources().iter().map(|s| source_shape(s)).collect();
+
+        shape = source_shapes
+            .and_then(|source_shapes| op.output_shape(&shape, &source_shapes))
+            .with_context(|| format!("invalid op {} ({})", i, op.name()))?;
+    }
+
+    Ok(shape)
+}
+
+/// Apply a list of ops to a tensor, in order.
+///
+/// `read_source` reads additional source tensors the ops need.
+pub fn apply(
+    ops: &[TensorOp],
+    mut tensor: Tensor,
+    mut read_source: impl FnMut(&str) -> Result<Tensor, Error>,
+) -> Result<Tensor, Error> {
+    for (i, op) in ops.iter().enumerate() {
+        let sources: Result<Vec<_>, _> = op.sources().iter().map(|s| read_source(s)).collect();
+
+        tensor = sources
+            .and_then(|sources| op.apply(tensor, sources))
+            .with_context(|| format!("failed to apply op {} ({})", i, op.name()))?;
+    }
+
+    Ok(tensor)
+}
+
+fn check_axis(axis: usize, shape: &[u64]) -> Result<(), Error> {
+    if axis >= shape.len() {
+        bail!("axis {} out of range for {:?}", axis, shape);
+    }
+
+    Ok(())
+}
+
+fn transpose_axes(axes: Option<&[usize]>, len: usize) -> Result<Vec<usize>, Error> {
+    let Some(axes) = axes else {
+        if len < 2 {
+            bail!("can't transpose a tensor with less than 2 axes");
+        }
+
+        let mut axes: Vec<_> = (0..len).collect();
+        axes.swap(len - 2, len - 1);
+        return Ok(axes);
+    };
+
+    let mut sorted = axes.to_vec();
+    sorted.sort();
+    if sorted != (0..len).collect::<Vec<_>>() {
+        bail!("axes {:?} aren't a permutation of {} axes", axes, len);
+    }
+
+    Ok(axes.to_vec())
+}
+
+/// Row-major strides of a shape, in values.
+fn strides(shape: &[u64]) -> Vec<u64> {
+    let mut strides = vec![1; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1];
+    }
+    strides
+}
+
+fn transpose(tensor: &Tensor, axes: &[usize], shape: &[u64]) -> Vec<f32> {
+    let source_strides = strides(&tensor.shape);
+    let strides: Vec<u64> = axes.iter().map(|axis| source_strides[*axis]).collect();
+
+    let mut values = Vec::with_capacity(tensor.values.len());
+    let mut index = vec![0u64; shape.len()];
+
+    for _ in 0..tensor.values.len() {
+        let offset: u64 = index.iter().zip(&strides).map(|(i, s)| i * s).sum();
+        values.push(tensor.values[offset as usize]);
+
+        // Step to the next index, the last axis changing fastest
+        for axis in (0..shape.len()).rev() {
+            index[axis] += 1;
+            if index[axis] < shape[axis] {
+                break;
+            }
+            index[axis] = 0;
+        }
+    }
+
+    values
+}
+
+fn concat(tensors: &[Tensor], axis: usize) -> Vec<f32> {
+    let outer: u64 = tensors[0].shape[..axis].iter().product();
+    let total = tensors.iter().map(|t| t.values.len()).sum();
+    let mut values = Vec::with_capacity(total);
+
+    for o in 0..outer as usize {
+        for tensor in tensors {
+            let inner: u64 = tensor.shape[axis..].iter().product();
+            let inner = inner as usize;
+            values.extend_from_slice(&tensor.values[o * inner..(o + 1) * inner]);
+        }
+    }
+
+    values
+}
+
+fn slice(tensor: &Tensor, axis: usize, start: u64, end: u64) -> Vec<f32> {
+    let outer: u64 = tensor.shape[..axis].iter().product();
+    let inner: u64 = tensor.shape[axis + 1..].iter().product();
+    let row = (tensor.shape[axis] * inner) as usize;
+    let (start, end) = ((start * inner) as usize, (end * inner) as usize);
+
+    let mut values = Vec::with_capacity((outer as usize) * (end - start));
+    for o in 0..outer as usize {
+        values.extend_from_slice(&tensor.values[o * row + start..o * row + end]);
+    }
+
+    values
+}
+
+fn map(mut values: Vec<f32>, f: impl Fn(f32) -> f32) -> Vec<f32> {
+    for value in &mut values {
+        *value = f(*value);
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::bail;
+
+    use crate::ops::{apply, output_shape, Tensor, TensorOp};
+
+    fn tensor(shape: &[u64]) -> Tensor {
+        let total: u64 = shape.iter().product();
+        Tensor {
+            shape: shape.to_vec(),
+            values: (0..total).map(|v| v as f32).collect(),
+        }
+    }
+
+    #[test]
+    fn transpose_and_slice() {
+        let ops = [
+            TensorOp::Transpose { axes: None },
+            TensorOp::Slice {
+                axis: 0,
+                start: 1,
+                end: 3,
+            },
+        ];
+
+        let result = apply(&ops, tensor(&[2, 3]), |_| bail!("no sources")).unwrap();
+        assert_eq!(result.shape, vec![2, 2]);
+        assert_eq!(result.values, vec![1.0, 4.0, 2.0, 5.0]);
+    }
+
+    #[test]
+    fn concat_sources() {
+        let ops = [
+            TensorOp::Unsqueeze { axis: 0 },
+            TensorOp::Concat {
+                axis: 1,
+                sources: vec!["b".to_string()],
+            },
+        ];
+
+        let shape = output_shape(&ops, &[2], |_| Ok(vec![1, 3])).unwrap();
+        assert_eq!(shape, vec![1, 5]);
+
+        let result = apply(&ops, tensor(&[2]), |_| Ok(tensor(&[1, 3]))).unwrap();
+        assert_eq!(result.values, vec![0.0, 1.0, 0.0, 1.0, 2.0]);
+
+        assert!(output_shape(&ops, &[2], |_| Ok(vec![2, 3])).is_err());
+    }
+
+    #[test]
+    fn decay() {
+        let ops = [TensorOp::Exp, TensorOp::Neg, TensorOp::Exp];
+
+        let result = apply(&ops, tensor(&[2]), |_| bail!("no sources")).unwrap();
+        assert_eq!(result.values, vec![(-1f32).exp(), (-(1f32.exp())).exp()]);
+    }
+
+    #[test]
+    fn reshape_and_squeeze() {
+        let ops = [
+            TensorOp::Reshape {
+                shape: vec![3, 1, 2],
+            },
+            TensorOp::Squeeze { axis: 1 },
+            TensorOp::Scale { factor: 0.5 },
+        ];
+
+        let result = apply(&ops, tensor(&[2, 3]), |_| bail!("no sources")).unwrap();
+        assert_eq!(result.shape, vec![3, 2]);
+        assert_eq!(result.values, vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.5]);
+    }
+
+    #[test]
+    fn transpose_axes() {
+        let ops = [TensorOp::Transpose {
+            axes: Some(vec![2, 0, 1]),
+        }];
+
+        let result = apply(&ops, tensor(&[2, 3, 4]), |_| bail!("no sources")).unwrap();
+        assert_eq!(result.shape, vec![4, 2, 3]);
+        assert_eq!(
+            result.values[..8],
+            [0.0, 4.0, 8.0, 12.0, 16.0, 20.0, 1.0, 5.0]
+        );
+    }
+
+    #[test]
+    fn invalid_ops() {
+        let shape = |op: TensorOp, shape: &[u64]| output_shape(&[op], shape, |_| bail!("none"));
+
+        assert!(shape(TensorOp::Reshape { shape: vec![4] }, &[2, 3]).is_err());
+        assert!(shape(TensorOp::Squeeze { axis: 0 }, &[2, 3]).is_err());
+        assert!(shape(TensorOp::Squeeze { axis: 2 }, &[2, 3]).is_err());
+        assert!(shape(TensorOp::Unsqueeze { axis: 3 }, &[2, 3]).is_err());
+        assert!(shape(TensorOp::Transpose { axes: None }, &[2]).is_err());
+
+        let axes = Some(vec![0, 0]);
+        assert!(shape(TensorOp::Transpose { axes }, &[2, 3]).is_err());
+
+        let slice = |axis, start, end| TensorOp::Slice { axis, start, end };
+        assert!(shape(slice(0, 1, 1), &[2, 3]).is_err());
+        assert!(shape(slice(0, 2, 1), &[2, 3]).is_err());
+        assert!(shape(slice(1, 0, 4), &[2, 3]).is_err());
+        assert!(shape(slice(2, 0, 1), &[2, 3]).is_err());
+        assert_eq!(shape(slice(1, 2, 3), &[2, 3]).unwrap(), vec![2, 1]);
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{bail, Context, Error};
+
+/// A tensor name pattern with named captures, like `rwkv.blocks.{n}.ln1.weight`.
+///
+/// A capture matches one or more characters, not including `.`.
+/// The same capture used more than once has to match the same value every time.
+///
+/// Unlike `gguf_swiss::glob_match`, captures are named so they can be substituted in target
+/// names, and don't cross `.`, so a capture only ever backtracks within a single name segment.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Capture(String),
+}
+
+impl Pattern {
+    pub fn parse(value: &str) -> Result<Self, Error> {
+        let mut parts = Vec::new();
+        let mut rest = value;
+
+        while !rest.is_empty() {
+            let Some(start) = rest.find('{') else {
+                parts.push(Part::Literal(rest.to_string()));
+                break;
+            };
+
+            if start != 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            } else if let Some(Part::Capture(_)) = parts.last() {
+                bail!("captures in {:?} must be separated by other text", value);
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .with_context(|| format!("unclosed capture in {:?}", value))?;
+            let name = &rest[start + 1..start + end];
+            if name.is_empty() || name.contains('{') {
+                bail!("invalid capture name in {:?}", value);
+            }
+
+            parts.push(Part::Capture(name.to_string()));
+            rest = &rest[start + end + 1..];
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Names of all captures in the pattern.
+    pub fn captures(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Capture(name) => Some(name.as_str()),
+            Part::Literal(_) => None,
+        })
+    }
+
+    /// Match a name against the pattern, getting the values of the captures by name.
+    pub fn matches(&self, value: &str) -> Option<Vec<(String, String)>> {
+        let mut captures = Vec::new();
+        match_parts(&self.parts, value, &mut captures).then_some(captures)
+    }
+
+    /// Format the pattern, substituting captures with their values.
+    pub fn format(&self, captures: &[(String, String)]) -> Result<String, Error> {
+        let mut value = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => value.push_str(text),
+                Part::Capture(name) => {
+                    let (_, capture) = captures
+                        .iter()
+                        .find(|(n, _)| n == name)
+                        .with_context(|| format!("unknown capture {{{}}}", name))?;
+                    value.push_str(capture);
+                }
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+fn match_parts(parts: &[Part], value: &str, captures: &mut Vec<(String, String)>) -> bool {
+    let Some((part, rest)) = parts.split_first() else {
+        return value.is_empty();
+    };
+
+    match part {
+        Part::Literal(text) => value
+            .strip_prefix(text.as_str())
+            .is_some_and(|value| match_parts(rest, value, captures)),
+        Part::Capture(name) => {
+            // Captures already matched elsewhere in the pattern must be the same
+            if let Some((_, capture)) = captures.iter().find(|(n, _)| n == name) {
+                let capture = capture.clone();
+                return value
+                    .strip_prefix(capture.as_str())
+                    .is_some_and(|value| match_parts(rest, value, captures));
+            }
+
+            let max = value.find('.').unwrap_or(value.len());
+            for end in (1..=max).filter(|end| value.is_char_boundary(*end)) {
+                captures.push((name.clone(), value[..end].to_string()));
+                if match_parts(rest, &value[end..], captures) {
+                    return true;
+                }
+                captures.pop();
+            }
+
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::Pattern;
+
+    #[test]
+    fn match_and_format() {
+        let source = Pattern::parse("model.layers.{n}.experts.{e}.w{i}").unwrap();
+        let target = Pattern::parse("blk.{n}.ffn_{i}.{e}").unwrap();
+
+        let captures = source.matches("model.layers.12.experts.3.w1").unwrap();
+        assert_eq!(target.format(&captures).unwrap(), "blk.12.ffn_1.3");
+
+        assert!(source.matches("model.layers.1.2.experts.3.w1").is_none());
+        assert!(source.matches("model.layers..experts.3.w1").is_none());
+
+        let repeated = Pattern::parse("{n}.a.{n}").unwrap();
+        assert!(repeated.matches("1.a.1").is_some());
+        assert!(repeated.matches("1.a.2").is_none());
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(Pattern::parse("a.{n").is_err());
+        assert!(Pattern::parse("a.{}").is_err());
+        assert!(Pattern::parse("a.{n}{m}").is_err());
+        assert!(Pattern::parse("blk.{n}").unwrap().format(&[]).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Condvar, Mutex,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, Error};
+
+/// Process items on multiple worker threads, and write the results in order on the current
+/// thread.
+///
+/// Every worker creates its own state with `init`, for example to open its own file handles.
+/// Items are only processed ahead of the writer while the total `cost` of the unwritten items
+/// stays within `budget`, bounding memory use. The next item to write is always processed, even
+/// if it exceeds the budget on its own.
+/// Processing stops at the first error, a panicking worker is returned as an error.
+pub fn run_ordered<T, S, R>(
+    items: &[T],
+    jobs: usize,
+    budget: u64,
+    cost: impl Fn(&T) -> u64,
+    init: impl Fn() -> Result<S, Error> + Sync,
+    process: impl Fn(&mut S, &T) -> Result<R, Error> + Sync,
+    mut write: impl FnMut(&T, R) -> Result<(), Error>,
+) -> Result<(), Error>
+where
+    T: Sync,
+    R: Send,
+{
+    // Total cost of all items before every index
+    let mut costs = vec![0];
+    for item in items {
+        costs.push(costs[costs.len() - 1] + cost(item));
+    }
+    let costs = &costs;
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let written = Mutex::new(0);
+    let written_changed = Condvar::new();
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            let sender = sender.clone();
+            let (next, stop, written, written_changed) = (&next, &stop, &written, &written_changed);
+            let (init, process) = (&init, &process);
+
+            scope.spawn(move || {
+                let mut state = match catch_panic(init) {
+                    Ok(state) => state,
+                    Err(error) => {
+                        let _ = sender.send((usize::MAX, Err(error)));
+                        return;
+                    }
+                };
+
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    if index >= items.len() {
+                        break;
+                    }
+
+                    // Wait until the writer has caught up enough
+                    let mut guard = written.lock().unwrap();
+                    while index > *guard
+                        && costs[index + 1] - costs[*guard] > budget
+                        && !stop.load(Ordering::SeqCst)
+                    {
+                        guard = written_changed.wait(guard).unwrap();
+                    }
+                    drop(guard);
+
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    let result = catch_panic(|| process(&mut state, &items[index]));
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Make sure all workers stop, even if waiting on the writer, also when `write` panics
+        let _guard = StopGuard {
+            stop: &stop,
+            written_changed: &written_changed,
+        };
+
+        write_ordered(items, &receiver, &written, &written_changed, &mut write)
+    })
+}
+
+struct StopGuard<'a> {
+    stop: &'a AtomicBool,
+    written_changed: &'a Condvar,
+}
+
+impl Drop for StopGuard<'_> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.written_changed.notify_all();
+    }
+}
+
+/// Run `f`, turning a panic into an error so the writer doesn't wait on it forever.
+fn catch_panic<R>(f: impl FnOnce() -> Result<R, Error>) -> Result<R, Error> {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(anyhow!("worker panicked: {}", panic_message(&*payload))))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+fn write_ordered<T, R>(
+    items: &[T],
+    receiver: &mpsc::Receiver<(usize, Result<R, Error>)>,
+    written: &Mutex<usize>,
+    written_changed: &Condvar,
+    write: &mut impl FnMut(&T, R) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+
+    while next < items.len() {
+        let (index, result) = receiver
+            .recv()
+            .map_err(|_| anyhow!("workers stopped unexpectedly"))?;
+        pending.insert(index, result?);
+
+        while let Some(result) = pending.remove(&next) {
+            write(&items[next], result)?;
+            next += 1;
+
+            *written.lock().unwrap() = next;
+            written_changed.notify_all();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use anyhow::bail;
+
+    use crate::pipeline::run_ordered;
+
+    #[test]
+    fn writes_in_order() {
+        let items: Vec<u32> = (0..100).collect();
+        let mut output = Vec::new();
+
+        run_ordered(
+            &items,
+            4,
+            3,
+            |_| 1,
+            || Ok(()),
+            |_, item| Ok(item * 2),
+            |_, result| {
+                output.push(result);
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        let expected: Vec<u32> = (0..100).map(|i| i * 2).collect();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn stops_on_error() {
+        let items: Vec<u32> = (0..100).collect();
+
+        let result = run_ordered(
+            &items,
+            4,
+            3,
+            |_| 1,
+            || Ok(()),
+            |_, item| {
+                if *item == 10 {
+                    bail!("failed");
+                }
+                Ok(*item)
+            },
+            |_, _| Ok(()),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn stops_on_panic() {
+        let items: Vec<u32> = (0..100).collect();
+        let mut written = 0;
+
+        let result = run_ordered(
+            &items,
+            4,
+            3,
+            |_| 1,
+            || Ok(()),
+            |_, item| {
+                if *item == 10 {
+                    panic!("failed");
+                }
+                Ok(*item)
+            },
+            |_, _| {
+                written += 1;
+                Ok(())
+            },
+        );
+        let error = result.unwrap_err();
+        assert!(error.to_string().contains("failed"));
+        assert!(written <= 10);
+
+        // Panics during init as well
+        let result = run_ordered(
+            &items,
+            4,
+            3,
+            |_| 1,
+            || -> Result<(), _> { panic!() },
+            |_, _| Ok(()),
+            |_, _| Ok(()),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn bounds_unwritten_cost() {
+        // Item costs, with one exceeding the budget on its own
+        let items: Vec<u64> = (0..100)
+            .map(|i| if i == 50 { 20 } else { i % 4 + 1 })
+            .collect();
+        let unwritten = Mutex::new(0);
+        let mut output = Vec::new();
+
+        run_ordered(
+            &items,
+            4,
+            8,
+            |item| *item,
+            || Ok(()),
+            |_, item| {
+                let mut unwritten = unwritten.lock().unwrap();
+                *unwritten += item;
+                assert!(*unwritten <= 8 || *unwritten == *item);
+                Ok(*item)
+            },
+            |_, result| {
+                *unwritten.lock().unwrap() -= result;
+                output.push(result);
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(output, items);
+    }
+}
@@ -2,11 +2,200 @@ use std::{
     collections::HashMap,
     fs::File,
     io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Error};
+use anyhow::{bail, Context, Error};
+use gguf_swiss::{decode_bf16, decode_f16, decode_f32, glob_match, TensorType};
+use half::f16;
+use serde::Deserialize;
 use serde_json::Value;
 
+/// Tensors from one or more safetensors files.
+pub struct StSource {
+    files: Vec<StFile>,
+    /// Index of the file containing every tensor.
+    tensors: HashMap<String, usize>,
+}
+
+pub struct StFile {
+    pub path: PathBuf,
+    pub file: File,
+    pub header: StHeader,
+}
+
+impl StSource {
+    /// Open a safetensors source, relative to the source root.
+    ///
+    /// The source can be a single `.safetensors` file, a `.safetensors.index.json` index of
+    /// sharded files, or a glob pattern matching file names, like `model-*.safetensors`.
+    pub fn open(source_root: &Path, source: &str) -> Result<Self, Error> {
+        let (paths, index) = if source.ends_with(".json") {
+            let (paths, tensors) = read_index(&source_root.join(source))?;
+            (paths, Some(tensors))
+        } else if source.contains(['*', '?']) {
+            (find_glob(source_root, source)?, None)
+        } else {
+            (vec![source_root.join(source)], None)
+        };
+
+        let mut files = Vec::new();
+        for path in paths {
+            let mut file = File::open(&path)
+                .with_context(|| format!("failed to open tensors source {}", path.display()))?;
+            let header = read_header(&mut file)
+                .with_context(|| format!("failed to read header of {}", path.display()))?;
+
+            files.push(StFile { path, file, header });
+        }
+
+        // Without an index, the first file containing a tensor is used
+        let tensors = index.unwrap_or_else(|| {
+            let mut tensors = HashMap::new();
+            for (index, file) in files.iter().enumerate().rev() {
+                for name in file.header.entries.keys() {
+                    tensors.insert(name.clone(), index);
+                }
+            }
+            tensors
+        });
+
+        Ok(Self { files, tensors })
+    }
+
+    /// Open the same files again without reading their headers, to read from another thread.
+    pub fn reopen(&self) -> Result<Self, Error> {
+        let mut files = Vec::new();
+        for file in &self.files {
+            let path = &file.path;
+            files.push(StFile {
+                path: path.clone(),
+                file: File::open(path)
+                    .with_context(|| format!("failed to open tensors source {}", path.display()))?,
+                header: file.header.clone(),
+            });
+        }
+
+        Ok(Self {
+            files,
+            tensors: self.tensors.clone(),
+        })
+    }
+
+    /// Names of all tensors, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tensors.keys().map(|name| name.as_str())
+    }
+
+    /// Find a tensor, getting the index of its file and its info.
+    pub fn tensor(&self, name: &str) -> Result<(usize, &StTensorInfo), Error> {
+        let file = *self
+            .tensors
+            .get(name)
+            .with_context(|| format!("unable to find source tensor {:?}", name))?;
+        let info = self.files[file]
+            .header
+            .entries
+            .get(name)
+            .with_context(|| format!("source tensor {:?} missing from its shard", name))?;
+        Ok((file, info))
+    }
+
+    /// Read all values of a tensor, decoded to f32.
+    pub fn read_values(&mut self, name: &str) -> Result<Vec<f32>, Error> {
+        let (file, info) = self.tensor(name)?;
+        let dtype = StDtype::parse(&info.data_type)
+            .with_context(|| format!("unable to read source tensor {:?}", name))?;
+
+        let total: u64 = info.data_shape.iter().product();
+        let size = info
+            .data_size()
+            .with_context(|| format!("invalid source tensor {:?}", name))?;
+        if total * dtype.size() as u64 != size {
+            bail!("source tensor {:?} data size doesn't match its shape", name);
+        }
+
+        let start = info.data_offsets[0];
+        let file = &mut self.files[file];
+        let mut data = vec![0u8; size as usize];
+        file.file
+            .seek(SeekFrom::Start(file.header.data_start + start))?;
+        file.file.read_exact(&mut data)?;
+
+        Ok(dtype.decode(&data))
+    }
+
+    pub fn header(&self, index: usize) -> &StHeader {
+        &self.files[index].header
+    }
+
+    pub fn file_mut(&mut self, index: usize) -> &mut StFile {
+        &mut self.files[index]
+    }
+}
+
+#[derive(Deserialize)]
+struct StIndex {
+    weight_map: HashMap<String, String>,
+}
+
+/// Read the paths of all shards in a safetensors index, relative to the index, and the index of
+/// the shard containing every tensor.
+fn read_index(path: &Path) -> Result<(Vec<PathBuf>, HashMap<String, usize>), Error> {
+    let text = std::fs::read_to_string(path).context("failed to open safetensors index")?;
+    let index: StIndex =
+        serde_json::from_str(&text).context("failed to parse safetensors index")?;
+
+    let mut names: Vec<_> = index.weight_map.values().cloned().collect();
+    names.sort();
+    names.dedup();
+
+    let shards: HashMap<&str, usize> = names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| (name.as_str(), index))
+        .collect();
+    let tensors = index
+        .weight_map
+        .iter()
+        .map(|(tensor, name)| (tensor.clone(), shards[name.as_str()]))
+        .collect();
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let paths = names.iter().map(|name| directory.join(name)).collect();
+    Ok((paths, tensors))
+}
+
+/// Find all files matching a glob pattern in their file name, sorted by name.
+fn find_glob(source_root: &Path, pattern: &str) -> Result<Vec<PathBuf>, Error> {
+    let pattern = source_root.join(pattern);
+    let directory = pattern.parent().context("invalid source pattern")?;
+    let name_pattern = pattern
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("invalid source pattern")?;
+
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(directory).context("failed to read source directory")? {
+        let path = entry?.path();
+        let matches = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| glob_match(name_pattern, name));
+
+        if matches && path.is_file() {
+            paths.push(path);
+        }
+    }
+
+    if paths.is_empty() {
+        bail!("no files match {:?}", name_pattern);
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
 pub fn read_header(file: &mut File) -> Result<StHeader, Error> {
     file.seek(SeekFrom::Start(0))?;
 
@@ -74,15 +263,282 @@ fn parse_entry(name: String, entry: Value) -> Result<(String, StTensorInfo), Err
     Ok((name, entry))
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StHeader {
     pub entries: HashMap<String, StTensorInfo>,
     pub data_start: u64,
 }
 
-#[derive(Debug)]
+/// Data type of a safetensors tensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StDtype {
+    Bool,
+    U8,
+    I8,
+    F8E5M2,
+    F8E4M3,
+    I16,
+    U16,
+    F16,
+    BF16,
+    I32,
+    U32,
+    F32,
+    F64,
+    I64,
+    U64,
+}
+
+impl StDtype {
+    pub fn parse(value: &str) -> Result<Self, Error> {
+        let value = match value {
+            "BOOL" => Self::Bool,
+            "U8" => Self::U8,
+            "I8" => Self::I8,
+            "F8_E5M2" => Self::F8E5M2,
+            "F8_E4M3" => Self::F8E4M3,
+            "I16" => Self::I16,
+            "U16" => Self::U16,
+            "F16" => Self::F16,
+            "BF16" => Self::BF16,
+            "I32" => Self::I32,
+            "U32" => Self::U32,
+            "F32" => Self::F32,
+            "F64" => Self::F64,
+            "I64" => Self::I64,
+            "U64" => Self::U64,
+            value => bail!("unsupported safetensors dtype {:?}", value),
+        };
+
+        Ok(value)
+    }
+
+    /// Size of a single value in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Bool | Self::U8 | Self::I8 | Self::F8E5M2 | Self::F8E4M3 => 1,
+            Self::I16 | Self::U16 | Self::F16 | Self::BF16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 | Self::I64 | Self::U64 => 8,
+        }
+    }
+
+    /// The GGUF tensor type with the same encoding, if any.
+    pub fn tensor_type(&self) -> Option<TensorType> {
+        match self {
+            Self::F16 => Some(TensorType::F16),
+            Self::F32 => Some(TensorType::F32),
+            Self::I8 => Some(TensorType::I8),
+            Self::I16 => Some(TensorType::I16),
+            Self::I32 => Some(TensorType::I32),
+            _ => None,
+        }
+    }
+
+    /// Decode raw little-endian data to f32 values.
+    pub fn decode(&self, data: &[u8]) -> Vec<f32> {
+        let values = data.chunks_exact(self.size());
+
+        match self {
+            Self::Bool => values.map(|b| (b[0] != 0) as u8 as f32).collect(),
+            Self::U8 => values.map(|b| b[0] as f32).collect(),
+            Self::I8 => values.map(|b| b[0] as i8 as f32).collect(),
+            Self::F8E5M2 => values.map(|b| decode_f8_e5m2(b[0])).collect(),
+            Self::F8E4M3 => values.map(|b| decode_f8_e4m3(b[0])).collect(),
+            Self::I16 => values
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32)
+                .collect(),
+            Self::U16 => values
+                .map(|b| u16::from_le_bytes([b[0], b[1]]) as f32)
+                .collect(),
+            Self::F16 => decode_f16(data),
+            Self::BF16 => decode_bf16(data),
+            Self::I32 => values
+                .map(|b| i32::from_le_bytes(b.try_into().unwrap()) as f32)
+                .collect(),
+            Self::U32 => values
+                .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as f32)
+                .collect(),
+            Self::F32 => decode_f32(data),
+            Self::F64 => values
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+                .collect(),
+            Self::I64 => values
+                .map(|b| i64::from_le_bytes(b.try_into().unwrap()) as f32)
+                .collect(),
+            Self::U64 => values
+                .map(|b| u64::from_le_bytes(b.try_into().unwrap()) as f32)
+                .collect(),
+        }
+    }
+}
+
+/// Decode an 8-bit float with 5 exponent bits and 2 mantissa bits, with infinities.
+fn decode_f8_e5m2(value: u8) -> f32 {
+    // This is the upper half of an f16
+    f16::from_bits((value as u16) << 8).to_f32()
+}
+
+/// Decode an 8-bit float with 4 exponent bits and 3 mantissa bits, without infinities.
+fn decode_f8_e4m3(value: u8) -> f32 {
+    let sign = if value & 0x80 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((value >> 3) & 0xF) as i32;
+    let mantissa = (value & 0x7) as f32;
+
+    let magnitude = match exponent {
+        0 => mantissa / 8.0 * 2f32.powi(-6),
+        15 if mantissa == 7.0 => f32::NAN,
+        _ => (1.0 + mantissa / 8.0) * 2f32.powi(exponent - 7),
+    };
+    sign * magnitude
+}
+
+#[derive(Debug, Clone)]
 pub struct StTensorInfo {
     pub data_type: String,
     pub data_offsets: [u64; 2],
     pub data_shape: Vec<u64>,
 }
+
+impl StTensorInfo {
+    /// Size of the tensor's data in bytes, from its data offsets.
+    pub fn data_size(&self) -> Result<u64, Error> {
+        let [start, end] = self.data_offsets;
+        end.checked_sub(start)
+            .context("data offsets end before they start")
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::{Path, PathBuf};
+
+    use serde_json::{json, Value};
+
+    use crate::safetensors::{decode_f8_e4m3, decode_f8_e5m2, StSource};
+
+    /// Write a safetensors file of F32 tensors, with values counting up from 0.
+    pub(crate) fn write_test_source(path: &Path, tensors: &[(&str, &[u64])]) {
+        let mut header = serde_json::Map::new();
+        let mut data = Vec::new();
+
+        for (name, shape) in tensors {
+            let total: u64 = shape.iter().product();
+            let start = data.len();
+            data.extend((0..total).flat_map(|v| (v as f32).to_le_bytes()));
+
+            let entry =
+                json!({ "dtype": "F32", "shape": shape, "data_offsets": [start, data.len()] });
+            header.insert(name.to_string(), entry);
+        }
+
+        write_source(path, &Value::Object(header), &data);
+    }
+
+    /// Write a safetensors file with a header and raw data.
+    pub(crate) fn write_source(path: &Path, header: &Value, data: &[u8]) {
+        let header = serde_json::to_vec(header).unwrap();
+        let mut file = (header.len() as u64).to_le_bytes().to_vec();
+        file.extend(header);
+        file.extend(data);
+        std::fs::write(path, file).unwrap();
+    }
+
+    fn test_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "gguf-swiss-pack-{}-st-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+
+        // Two shards that both contain "b", and an unrelated file
+        let first = root.join("model-00001-of-00002.safetensors");
+        write_test_source(&first, &[("a", &[2]), ("b", &[3])]);
+        let second = root.join("model-00002-of-00002.safetensors");
+        write_test_source(&second, &[("b", &[4])]);
+        write_test_source(&root.join("other.safetensors"), &[("c", &[1])]);
+
+        root
+    }
+
+    #[test]
+    fn open_index() {
+        let root = test_root("index");
+        let index = json!({
+            "metadata": { "total_size": 36 },
+            "weight_map": {
+                "a": "model-00001-of-00002.safetensors",
+                "b": "model-00002-of-00002.safetensors",
+            },
+        });
+        std::fs::write(root.join("model.safetensors.index.json"), index.to_string()).unwrap();
+
+        let mut source = StSource::open(&root, "model.safetensors.index.json").unwrap();
+        let mut names: Vec<_> = source.names().collect();
+        names.sort();
+        assert_eq!(names, ["a", "b"]);
+
+        // The index decides which shard a tensor is read from
+        assert_eq!(source.tensor("b").unwrap().0, 1);
+        assert_eq!(source.read_values("b").unwrap(), [0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(source.read_values("a").unwrap(), [0.0, 1.0]);
+        assert!(source.tensor("c").is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn open_glob() {
+        let root = test_root("glob");
+
+        let mut source = StSource::open(&root, "model-*.safetensors").unwrap();
+        let mut names: Vec<_> = source.names().collect();
+        names.sort();
+        assert_eq!(names, ["a", "b"]);
+
+        // Without an index, the first matching file containing a tensor is used
+        assert_eq!(source.tensor("b").unwrap().0, 0);
+        assert_eq!(source.read_values("b").unwrap(), [0.0, 1.0, 2.0]);
+
+        let error = StSource::open(&root, "missing-*.safetensors")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "no files match \"missing-*.safetensors\""
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn invalid_offsets() {
+        let root = test_root("offsets");
+
+        let header = json!({ "a": { "dtype": "F32", "shape": [2], "data_offsets": [8, 0] } });
+        write_source(&root.join("model.safetensors"), &header, &[0; 8]);
+
+        let mut source = StSource::open(&root, "model.safetensors").unwrap();
+        let error = source.read_values("a").unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "invalid source tensor \"a\": data offsets end before they start"
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn decode_f8() {
+        assert_eq!(decode_f8_e4m3(0x38), 1.0);
+        assert_eq!(decode_f8_e4m3(0xC4), -3.0);
+        assert_eq!(decode_f8_e4m3(0x01), 2f32.powi(-9));
+        assert_eq!(decode_f8_e4m3(0x7E), 448.0);
+        assert!(decode_f8_e4m3(0x7F).is_nan());
+
+        assert_eq!(decode_f8_e5m2(0x3C), 1.0);
+        assert_eq!(decode_f8_e5m2(0xC2), -3.0);
+        assert_eq!(decode_f8_e5m2(0x7C), f32::INFINITY);
+    }
+}
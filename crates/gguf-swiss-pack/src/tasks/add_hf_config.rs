@@ -0,0 +1,396 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{bail, Context, Error};
+use gguf_swiss::{MetadataType, MetadataValue};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use toml::Table;
+
+use crate::tasks::{PackTask, ProcessContext};
+
+/// Add model hyperparameters read from a HuggingFace `config.json`.
+pub struct AddHfConfigTask {
+    manifest: AddHfConfigManifest,
+}
+
+impl AddHfConfigTask {
+    pub fn new(manifest: &Table) -> Result<Self, Error> {
+        let manifest = manifest
+            .clone()
+            .try_into()
+            .context("failed to parse manifest")?;
+
+        let value = Self { manifest };
+        Ok(value)
+    }
+}
+
+impl PackTask for AddHfConfigTask {
+    fn process(&mut self, ctx: &mut ProcessContext) -> Result<(), Error> {
+        let m = &self.manifest;
+
+        let path = ctx.source_root().join(&m.source);
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let config: Value = serde_json::from_str(&text).context("failed to parse config")?;
+
+        // The architecture defaults to the config's model type
+        let architecture = match &m.architecture {
+            Some(architecture) => architecture.clone(),
+            None => config
+                .get("model_type")
+                .and_then(|v| v.as_str())
+                .context("no architecture given, and config has no \"model_type\"")?
+                .to_string(),
+        };
+        let key = |key: &str| key.replace("{arch}", &architecture);
+
+        let mut metadata = Vec::new();
+        let architecture_value = MetadataValue::String(architecture.as_bytes().to_vec());
+        set_metadata(
+            &mut metadata,
+            "general.architecture".to_string(),
+            architecture_value,
+        );
+
+        // Metadata from the manifest overrides fields mapped to the same key
+        let mut overrides = Vec::new();
+        for (name, value) in &m.metadata {
+            let (value, ty) = match value {
+                MetadataManifest::Typed { value, ty } => (value, Some(*ty)),
+                MetadataManifest::Value(value) => (value, None),
+            };
+
+            // `{arch}` is resolved before overrides, so the architecture can't be one
+            let name = key(name);
+            if name == "general.architecture" {
+                bail!("set the architecture with \"architecture\", not in \"metadata\"");
+            }
+
+            let value = serde_json::to_value(value)?;
+            let value = to_metadata(&value, ty)
+                .with_context(|| format!("invalid value for metadata {:?}", name))?;
+            overrides.push((name, value));
+        }
+
+        let mut mapped = HashMap::new();
+        for (path, target, ty) in field_mappings(m, &config, key) {
+            if target == "general.architecture" {
+                bail!("set the architecture with \"architecture\", not in \"fields\"");
+            }
+
+            if let Some(other) = mapped.insert(target.clone(), path) {
+                bail!(
+                    "config fields {:?} and {:?} both map to {:?}",
+                    other,
+                    path,
+                    target
+                );
+            }
+
+            if overrides.iter().any(|(k, _)| *k == target) {
+                continue;
+            }
+
+            let value = find_field(&config, path)
+                .with_context(|| format!("config has no field {:?}", path))?;
+            let value = to_metadata(value, ty)
+                .with_context(|| format!("invalid value for config field {:?}", path))?;
+            set_metadata(&mut metadata, target, value);
+        }
+
+        for (key, value) in overrides {
+            set_metadata(&mut metadata, key, value);
+        }
+
+        for (key, value) in metadata {
+            ctx.push_metadata_value(key, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fields mapped by default, if in the config and not mapped to the same key by the manifest.
+const DEFAULT_FIELDS: &[(&str, &str)] = &[
+    ("hidden_size", "{arch}.embedding_length"),
+    ("num_hidden_layers", "{arch}.block_count"),
+    ("vocab_size", "{arch}.vocab_size"),
+    ("layer_norm_epsilon", "{arch}.attention.layer_norm_epsilon"),
+];
+
+/// Get the config fields to map, with their metadata keys and types.
+fn field_mappings<'a>(
+    m: &'a AddHfConfigManifest,
+    config: &Value,
+    key: impl Fn(&str) -> String,
+) -> Vec<(&'a str, String, Option<MetadataType>)> {
+    let mut fields: Vec<_> = m
+        .fields
+        .iter()
+        .map(|(path, mapping)| match mapping {
+            FieldMapping::Typed { key: target, ty } => (path.as_str(), key(target), Some(*ty)),
+            FieldMapping::Key(target) => (path.as_str(), key(target), None),
+        })
+        .collect();
+
+    let keys: HashSet<_> = fields.iter().map(|(_, target, _)| target.clone()).collect();
+    for (path, target) in DEFAULT_FIELDS {
+        let target = key(target);
+        let mapped = m.fields.contains_key(*path) || keys.contains(&target);
+
+        if !mapped && find_field(config, path).is_some() {
+            fields.push((path, target, None));
+        }
+    }
+
+    fields
+}
+
+/// Set a metadata value, replacing the value of the same key if already set.
+fn set_metadata(metadata: &mut Vec<(String, MetadataValue)>, key: String, value: MetadataValue) {
+    match metadata.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, existing)) => *existing = value,
+        None => metadata.push((key, value)),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct AddHfConfigManifest {
+    /// Path of the config, relative to the source root.
+    #[serde(default = "default_source")]
+    pub source: String,
+
+    /// Architecture of the model, defaults to `model_type` in the config.
+    pub architecture: Option<String>,
+
+    /// Config fields by dotted path, mapped to metadata keys.
+    /// `{arch}` in keys is replaced with the architecture.
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldMapping>,
+
+    /// Metadata to add, overriding fields mapped to the same key.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, MetadataManifest>,
+}
+
+fn default_source() -> String {
+    "config.json".to_string()
+}
+
+/// A metadata key, with the type inferred from the value, or a key and an explicit type.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum FieldMapping {
+    Typed {
+        key: String,
+        #[serde(rename = "type", deserialize_with = "deserialize_type")]
+        ty: MetadataType,
+    },
+    Key(String),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum MetadataManifest {
+    Typed {
+        value: toml::Value,
+        #[serde(rename = "type", deserialize_with = "deserialize_type")]
+        ty: MetadataType,
+    },
+    Value(toml::Value),
+}
+
+/// Deserialize a type by its lowercase name, like `uint32`.
+fn deserialize_type<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MetadataType, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
+
+/// Find a field in nested objects, by a path like `text_config.hidden_size`.
+fn find_field<'a>(config: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(config, |value, name| value.get(name))
+}
+
+/// Convert a value to metadata of a type, or infer the type if not given.
+///
+/// Integers are inferred as `uint32` or `int32` if they fit, and floats as `float32`, as is
+/// common for hyperparameters.
+fn to_metadata(value: &Value, ty: Option<MetadataType>) -> Result<MetadataValue, Error> {
+    let ty = match ty {
+        Some(ty) => ty,
+        None => infer_type(value)?,
+    };
+
+    let text = match value {
+        Value::String(value) => value.clone(),
+        Value::Bool(_) | Value::Number(_) => value.to_string(),
+        _ => bail!("only numbers, bools and strings are supported"),
+    };
+
+    MetadataValue::parse(ty, &text)
+}
+
+fn infer_type(value: &Value) -> Result<MetadataType, Error> {
+    let ty = match value {
+        Value::Bool(_) => MetadataType::Bool,
+        Value::String(_) => MetadataType::String,
+        Value::Number(number) => {
+            if let Some(value) = number.as_u64() {
+                if value <= u32::MAX as u64 {
+                    MetadataType::UInt32
+                } else {
+                    MetadataType::UInt64
+                }
+            } else if let Some(value) = number.as_i64() {
+                if i32::try_from(value).is_ok() {
+                    MetadataType::Int32
+                } else {
+                    MetadataType::Int64
+                }
+            } else {
+                MetadataType::Float32
+            }
+        }
+        _ => bail!("only numbers, bools and strings are supported"),
+    };
+
+    Ok(ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use gguf_swiss::{MetadataType, MetadataValue};
+    use serde_json::json;
+    use toml::Table;
+
+    use crate::tasks::{
+        add_hf_config::{find_field, to_metadata, AddHfConfigTask},
+        PackTask, ProcessContext,
+    };
+
+    fn process(name: &str, manifest: &str) -> Result<Vec<(String, MetadataValue)>, anyhow::Error> {
+        let root =
+            std::env::temp_dir().join(format!("gguf-swiss-pack-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&root).unwrap();
+        let config = json!({
+            "model_type": "llama",
+            "hidden_size": 2048,
+            "num_hidden_layers": 24,
+            "n_embd": 4096,
+            "rms_norm_eps": 1e-6,
+        });
+        std::fs::write(root.join("config.json"), config.to_string()).unwrap();
+
+        let manifest: Table = toml::from_str(manifest).unwrap();
+        let mut ctx = ProcessContext {
+            source_root: root.clone(),
+            metadata: Vec::new(),
+            tensors: Vec::new(),
+        };
+        let result = AddHfConfigTask::new(&manifest).and_then(|mut task| task.process(&mut ctx));
+        std::fs::remove_dir_all(&root).unwrap();
+
+        result.map(|_| ctx.metadata)
+    }
+
+    fn string(value: &str) -> MetadataValue {
+        MetadataValue::String(value.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn convert_values() {
+        let config = json!({ "hidden_size": 2048, "text_config": { "eps": 1e-5, "bias": -1 } });
+
+        let value = find_field(&config, "hidden_size").unwrap();
+        assert_eq!(
+            to_metadata(value, None).unwrap(),
+            MetadataValue::UInt32(2048)
+        );
+        assert_eq!(
+            to_metadata(value, Some(MetadataType::UInt64)).unwrap(),
+            MetadataValue::UInt64(2048)
+        );
+        assert!(to_metadata(value, Some(MetadataType::UInt8)).is_err());
+
+        let value = find_field(&config, "text_config.eps").unwrap();
+        assert_eq!(
+            to_metadata(value, None).unwrap(),
+            MetadataValue::Float32(1e-5)
+        );
+
+        let value = find_field(&config, "text_config.bias").unwrap();
+        assert_eq!(to_metadata(value, None).unwrap(), MetadataValue::Int32(-1));
+
+        assert!(find_field(&config, "text_config.missing").is_none());
+    }
+
+    #[test]
+    fn default_fields() {
+        let metadata = process("hf-defaults", "").unwrap();
+
+        // Only fields in the config are mapped
+        let expected = [
+            ("general.architecture", string("llama")),
+            ("llama.embedding_length", MetadataValue::UInt32(2048)),
+            ("llama.block_count", MetadataValue::UInt32(24)),
+        ];
+        let expected: Vec<_> = expected.map(|(k, v)| (k.to_string(), v)).into();
+        assert_eq!(metadata, expected);
+    }
+
+    #[test]
+    fn override_fields() {
+        let manifest = r#"
+            architecture = "rwkv6"
+            fields.n_embd = "{arch}.embedding_length"
+            fields.rms_norm_eps = { key = "{arch}.attention.layer_norm_rms_epsilon", type = "float64" }
+
+            [metadata]
+            "{arch}.block_count" = { value = 12, type = "uint64" }
+            "{arch}.context_length" = 1024
+            "#;
+        let metadata = process("hf-overrides", manifest).unwrap();
+
+        // Overrides replace entries in place, every key is only added once
+        let expected = [
+            ("general.architecture", string("rwkv6")),
+            ("rwkv6.embedding_length", MetadataValue::UInt32(4096)),
+            (
+                "rwkv6.attention.layer_norm_rms_epsilon",
+                MetadataValue::Float64(1e-6),
+            ),
+            ("rwkv6.block_count", MetadataValue::UInt64(12)),
+            ("rwkv6.context_length", MetadataValue::UInt32(1024)),
+        ];
+        let expected: Vec<_> = expected.map(|(k, v)| (k.to_string(), v)).into();
+        assert_eq!(metadata, expected);
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let duplicate = r#"
+            fields.hidden_size = "{arch}.width"
+            fields.n_embd = "{arch}.width"
+            "#;
+        let error = process("hf-duplicate", duplicate).unwrap_err();
+        assert!(error.to_string().contains("both map to \"llama.width\""));
+
+        let missing = r#"fields.missing = "{arch}.missing""#;
+        assert!(process("hf-missing", missing).is_err());
+
+        let invalid = r#"metadata.a = { value = 1.5, type = "uint32" }"#;
+        assert!(process("hf-invalid", invalid).is_err());
+
+        let unknown = r#"metadata.a = { value = 1, type = "u32" }"#;
+        assert!(process("hf-unknown", unknown).is_err());
+
+        // The architecture is only set by its own field, as it resolves `{arch}`
+        let architecture = r#"metadata."general.architecture" = "rwkv6""#;
+        assert!(process("hf-architecture", architecture).is_err());
+
+        let architecture = r#"fields.model_type = "general.architecture""#;
+        assert!(process("hf-architecture-field", architecture).is_err());
+    }
+}
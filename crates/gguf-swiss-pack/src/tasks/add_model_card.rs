@@ -24,10 +24,10 @@ impl PackTask for AddModelCardTask {
     fn process(&mut self, ctx: &mut ProcessContext) -> Result<(), Error> {
         let m = &self.manifest;
 
-        ctx.push_metadata_str("general.name", &m.name);
-        ctx.push_metadata_str("general.author", &m.author);
-        ctx.push_metadata_str("general.description", &m.description);
-        ctx.push_metadata_str("general.license", &m.license);
+        ctx.push_metadata_str("general.name", &m.name)?;
+        ctx.push_metadata_str("general.author", &m.author)?;
+        ctx.push_metadata_str("general.description", &m.description)?;
+        ctx.push_metadata_str("general.license", &m.license)?;
 
         Ok(())
     }
@@ -48,13 +48,13 @@ impl PackTask for ConvertRwkvTokenizerTask {
         }
 
         // Insert tokenizer into metadata
-        ctx.push_metadata_str("tokenizer.ggml.model", "rwkv");
+        ctx.push_metadata_str("tokenizer.ggml.model", "rwkv")?;
 
         let vocab_value = MetadataValue::Array(MetadataArray::String(vocab));
-        ctx.push_metadata_value("tokenizer.ggml.tokens", vocab_value);
+        ctx.push_metadata_value("tokenizer.ggml.tokens", vocab_value)?;
 
         let vocab_value = MetadataValue::Array(MetadataArray::UInt32(token_type));
-        ctx.push_metadata_value("tokenizer.ggml.token_type", vocab_value);
+        ctx.push_metadata_value("tokenizer.ggml.token_type", vocab_value)?;
 
         Ok(())
     }
@@ -1,22 +1,35 @@
 use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
     fs::File,
     io::{Read, Seek, SeekFrom, Write},
-    path::Path,
 };
 
 use anyhow::{bail, Context, Error};
-use gguf_swiss::{align_offset, TensorDimensions, TensorInfo, TensorType};
+use gguf_swiss::{
+    align_offset, encode_f16, encode_f32, write_padding, write_tensor_data, TensorDimensions,
+    TensorInfo, TensorType,
+};
 use serde::Deserialize;
 use toml::Table;
 
 use crate::{
-    safetensors::{self, StHeader},
-    tasks::{PackTask, ProcessContext},
+    ops::{self, Tensor, TensorOp},
+    pattern::Pattern,
+    pipeline,
+    safetensors::{StDtype, StSource},
+    tasks::{PackTask, ProcessContext, WriteContext},
 };
 
+/// Amount of scalars to convert at a time, per worker.
+const CHUNK_SCALARS: u64 = 1024 * 1024;
+
 pub struct ConvertSafetensorsTask {
     manifest: ConvertSafetensorsManifest,
     tensors: Vec<ConvertTensorInfo>,
+
+    /// Source opened when processing, reused to write the tensors.
+    source: Option<StSource>,
 }
 
 impl ConvertSafetensorsTask {
@@ -29,11 +42,12 @@ impl ConvertSafetensorsTask {
         let value = Self {
             manifest,
             tensors: Vec::new(),
+            source: None,
         };
         Ok(value)
     }
 
-    fn expand_tensors(&self) -> Result<Vec<(String, String, TensorManifest)>, Error> {
+    fn expand_tensors(&self, source: &StSource) -> Result<Vec<(String, TensorManifest)>, Error> {
         let mut values = Vec::new();
 
         for (name, value) in &self.manifest.tensors {
@@ -55,9 +69,8 @@ impl ConvertSafetensorsTask {
                     for i in start..end {
                         let is = i.to_string();
                         let target_name = name.replace('$', &is);
-                        let source_name = manifest.source.replace('$', &is);
-
-                        values.push((target_name, source_name, manifest.clone()));
+                        let manifest = manifest.map_sources(|s| Ok(s.replace('$', &is)))?;
+                        values.push((target_name, manifest));
                     }
                 }
 
@@ -69,7 +82,36 @@ impl ConvertSafetensorsTask {
                 .clone()
                 .try_into()
                 .context("failed to parse manifest")?;
-            values.push((name.clone(), manifest.source.clone(), manifest));
+            values.push((name.clone(), manifest));
+        }
+
+        // Tensors without a source override the tensors mapped by rules
+        let (mut values, overrides): (Vec<_>, Vec<_>) = values
+            .into_iter()
+            .partition(|(_, manifest)| manifest.source.is_some());
+        let mut overrides: HashMap<_, _> = overrides.into_iter().collect();
+
+        let explicit_count = values.len();
+        let explicit: HashSet<_> = values.iter().map(|(name, _)| name.clone()).collect();
+        let mut mapped = HashSet::new();
+        for rule in &self.manifest.rules {
+            expand_rule(&mut values, &explicit, &mut mapped, source, rule)
+                .with_context(|| format!("failed to apply rule {:?}", rule.source))?;
+        }
+
+        for (name, manifest) in &mut values[explicit_count..] {
+            if let Some(value) = overrides.remove(name) {
+                manifest
+                    .apply_override(value)
+                    .with_context(|| format!("invalid override of tensor {:?}", name))?;
+            }
+        }
+
+        if let Some(name) = overrides.keys().min() {
+            bail!(
+                "tensor {:?} has no source, and isn't mapped by a rule",
+                name
+            );
         }
 
         Ok(values)
@@ -78,12 +120,16 @@ impl ConvertSafetensorsTask {
     fn prepare_tensor(
         &mut self,
         ctx: &mut ProcessContext,
+        source: &StSource,
         next_offset: &mut u64,
-        manifest: &TensorManifest,
         target_name: String,
-        source_name: String,
+        manifest: TensorManifest,
     ) -> Result<(), Error> {
-        let (tensor_type, scalar_size) = match manifest.ty.as_str() {
+        let source_name = manifest.source.context("tensor has no source")?;
+        let ty = manifest
+            .ty
+            .with_context(|| format!("tensor {:?} has no type", target_name))?;
+        let (tensor_type, scalar_size) = match ty.as_str() {
             "F16" => (TensorType::F16, 2),
             "F32" => (TensorType::F32, 4),
             _ => {
@@ -91,31 +137,44 @@ impl ConvertSafetensorsTask {
             }
         };
 
-        // Convert from manifest dimensions to gguf-swiss
-        let mut dimensions = TensorDimensions::default();
-        for (i, value) in manifest.dimensions.iter().enumerate() {
-            dimensions.0[i] = *value;
+        // Infer dimensions from the source shape, after any ops
+        let (_, source_info) = source.tensor(&source_name)?;
+        let shape = ops::output_shape(&manifest.ops, &source_info.data_shape, |name| {
+            Ok(source.tensor(name)?.1.data_shape.clone())
+        })
+        .with_context(|| format!("unable to transform source tensor {:?}", source_name))?;
+        let dimensions = TensorDimensions::from_width_last(&shape)?;
+
+        // Dimensions in the manifest are optional, but must match if given
+        if let Some(expected) = &manifest.dimensions {
+            if expected[..] != dimensions.0[..dimensions.count()] {
+                bail!(
+                    "tensor {:?} has dimensions {:?} in the manifest, but source {:?} has shape {:?}, which is {} in GGUF order",
+                    target_name,
+                    expected,
+                    source_name,
+                    shape,
+                    dimensions
+                );
+            }
         }
 
-        // Record a conversion task
-        let scalars = dimensions.total();
-        let tensor_info = ConvertTensorInfo {
-            name: target_name.clone(),
-            source: source_name,
-            dimensions,
-            offset: *next_offset,
-            ty: tensor_type,
-        };
-        self.tensors.push(tensor_info);
-
         // Record tensor entry
-        let value = TensorInfo {
+        let scalars = dimensions.total();
+        let info = TensorInfo {
             name: target_name,
             tensor_type,
             dimensions,
             offset: *next_offset,
         };
-        ctx.tensors.push(value);
+        ctx.tensors.push(info.clone());
+
+        // Record a conversion task
+        self.tensors.push(ConvertTensorInfo {
+            info,
+            source: source_name,
+            ops: manifest.ops,
+        });
 
         // Figure out the next offset
         *next_offset += scalars * scalar_size;
@@ -129,180 +188,644 @@ impl PackTask for ConvertSafetensorsTask {
     fn process(&mut self, ctx: &mut ProcessContext) -> Result<(), Error> {
         let mut next_offset = 0;
 
-        let expanded = self.expand_tensors()?;
+        let source = StSource::open(ctx.source_root(), &self.manifest.source)?;
+        let expanded = self.expand_tensors(&source)?;
+
+        for name in unmapped_sources(&source, &expanded) {
+            println!("unmapped source tensor {:?}", name);
+        }
 
         // Process expanded tensors
-        for (target_name, source_name, value) in expanded {
-            self.prepare_tensor(ctx, &mut next_offset, &value, target_name, source_name)?;
+        for (target_name, value) in expanded {
+            self.prepare_tensor(ctx, &source, &mut next_offset, target_name, value)?;
         }
 
+        self.source = Some(source);
+
         Ok(())
     }
 
-    fn write_tensors(&mut self, source_root: &Path, output: &mut File) -> Result<(), Error> {
+    fn write_tensors(&mut self, ctx: &WriteContext, output: &mut File) -> Result<(), Error> {
         let data_start = write_padding(output)?;
 
-        // Open tensors source file
-        let tensors_source_file_path = source_root.join(&self.manifest.source);
-        let mut tensors_source_file =
-            File::open(tensors_source_file_path).context("failed to open tensors source")?;
-
-        // Handle all tensor conversion tasks
-        let source_header = safetensors::read_header(&mut tensors_source_file)?;
-        for tensor in &self.tensors {
-            convert_tensor(
-                output,
-                data_start,
-                &mut tensors_source_file,
-                &source_header,
-                tensor,
-            )?;
+        // Divide all tensors into chunks to convert
+        let source = self.source.take().context("tensors not processed")?;
+        let mut chunks = Vec::new();
+        for (index, tensor) in self.tensors.iter().enumerate() {
+            plan_chunks(&mut chunks, &source, index, tensor)?;
         }
 
-        Ok(())
+        // Convert chunks in parallel, with every worker reading from its own source files, and
+        // about four chunks of memory per worker
+        let budget = ctx.jobs() as u64 * 4 * CHUNK_SCALARS * 4;
+        pipeline::run_ordered(
+            &chunks,
+            ctx.jobs(),
+            budget,
+            |chunk| chunk.cost(&self.tensors[chunk.tensor]),
+            || source.reopen(),
+            |source, chunk| convert_chunk(source, chunk, &self.tensors[chunk.tensor]),
+            |chunk, data| {
+                let tensor = &self.tensors[chunk.tensor];
+
+                if chunk.first {
+                    println!("converting tensor {:?}", tensor.info.name);
+                    write_tensor_data(output, data_start, &tensor.info, &data)?;
+                } else {
+                    output.write_all(&data)?;
+                }
+
+                Ok(())
+            },
+        )
     }
 }
 
 #[derive(Deserialize, Debug)]
 struct ConvertSafetensorsManifest {
+    /// Safetensors file, `.safetensors.index.json` index, or glob pattern of files.
     pub source: String,
+
+    #[serde(default)]
     pub tensors: Table,
+
+    /// Rules mapping source tensors by pattern, applied after the explicitly listed tensors.
+    #[serde(default)]
+    pub rules: Vec<MappingRule>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
-pub struct TensorManifest {
+/// Maps every source tensor matching a pattern to a target tensor.
+#[derive(Deserialize, Debug)]
+struct MappingRule {
+    /// Source tensor name pattern, with `{name}` captures.
     pub source: String,
 
+    /// Target tensor name, with the captures of the source substituted.
+    pub target: String,
+
+    /// Default type of the mapped tensors.
+    ///
+    /// Listed tensors with the same name take precedence, or override only the type if listed
+    /// without a source.
     #[serde(rename = "type")]
     pub ty: String,
 
-    pub dimensions: Vec<u64>,
+    /// Transformations to apply, captures are substituted in source tensor names.
+    #[serde(default)]
+    pub ops: Vec<TensorOp>,
 }
 
-fn write_padding(target: &mut File) -> Result<u64, Error> {
-    let current = target.stream_position()?;
+#[derive(Deserialize, Debug, Clone)]
+pub struct TensorManifest {
+    /// Source tensor, left out to override a tensor mapped by a rule.
+    #[serde(default)]
+    pub source: Option<String>,
+
+    #[serde(rename = "type", default)]
+    pub ty: Option<String>,
+
+    /// Dimensions in GGUF order, inferred from the source if not given.
+    #[serde(default)]
+    pub dimensions: Option<Vec<u64>>,
 
-    let padded = align_offset(current);
-    let padding = padded - current;
+    /// Transformations to apply to the source tensor, in order.
+    #[serde(default)]
+    pub ops: Vec<TensorOp>,
+}
+
+impl TensorManifest {
+    /// Map the names of source tensors, for expansion macros and mapping rules.
+    fn map_sources(&self, mut f: impl FnMut(&str) -> Result<String, Error>) -> Result<Self, Error> {
+        let ops: Result<_, _> = self.ops.iter().map(|op| op.map_sources(&mut f)).collect();
 
-    if padding != 0 {
-        let padding = vec![0u8; padding as usize];
-        target.write_all(&padding)?;
+        let value = Self {
+            source: self.source.as_deref().map(&mut f).transpose()?,
+            ty: self.ty.clone(),
+            dimensions: self.dimensions.clone(),
+            ops: ops?,
+        };
+        Ok(value)
     }
 
-    Ok(padded)
+    /// Override the type and dimensions of a tensor mapped by a rule.
+    fn apply_override(&mut self, value: TensorManifest) -> Result<(), Error> {
+        if !value.ops.is_empty() {
+            bail!("ops can't be overridden without a source");
+        }
+
+        if let Some(ty) = value.ty {
+            self.ty = Some(ty);
+        }
+        if let Some(dimensions) = value.dimensions {
+            self.dimensions = Some(dimensions);
+        }
+
+        Ok(())
+    }
 }
 
-fn convert_tensor(
-    target: &mut File,
-    data_start: u64,
-    source_file: &mut File,
-    source_header: &StHeader,
-    tensor: &ConvertTensorInfo,
+/// Add a target tensor for every source tensor matching a rule, sorted by their captures.
+///
+/// Targets in `explicit` are skipped, targets already in `mapped` by another match are an error.
+fn expand_rule(
+    values: &mut Vec<(String, TensorManifest)>,
+    explicit: &HashSet<String>,
+    mapped: &mut HashSet<String>,
+    source: &StSource,
+    rule: &MappingRule,
 ) -> Result<(), Error> {
-    println!("converting tensor {:?}", tensor.name);
+    let source_pattern = Pattern::parse(&rule.source)?;
+    let target_pattern = Pattern::parse(&rule.target)?;
+
+    for capture in target_pattern.captures() {
+        if !source_pattern.captures().any(|c| c == capture) {
+            bail!("capture {{{}}} of the target isn't in the source", capture);
+        }
+    }
 
-    let scalars = read_source_scalars(source_file, source_header, tensor)?;
-    write_scalars(target, data_start, tensor, scalars)?;
+    let mut matches: Vec<_> = source
+        .names()
+        .filter_map(|name| Some((name, source_pattern.matches(name)?)))
+        .collect();
+    matches.sort_by(|(_, a), (_, b)| compare_captures(a, b));
+
+    for (name, captures) in matches {
+        let target_name = target_pattern.format(&captures)?;
+
+        if explicit.contains(&target_name) {
+            continue;
+        }
+        if !mapped.insert(target_name.clone()) {
+            bail!("multiple source tensors map to {:?}", target_name);
+        }
+
+        let ops: Result<_, Error> = rule
+            .ops
+            .iter()
+            .map(|op| op.map_sources(|s| Pattern::parse(s)?.format(&captures)))
+            .collect();
+
+        let manifest = TensorManifest {
+            source: Some(name.to_string()),
+            ty: Some(rule.ty.clone()),
+            dimensions: None,
+            ops: ops?,
+        };
+        values.push((target_name, manifest));
+    }
 
     Ok(())
 }
 
-fn read_source_scalars(
-    source_file: &mut File,
-    source_header: &StHeader,
-    tensor: &ConvertTensorInfo,
-) -> Result<Vec<f32>, Error> {
-    let source_info = &source_header
-        .entries
-        .get(&tensor.source)
-        // TODO: This error should probably be more informative
-        .context("unable to find source tensor")?;
+/// Source tensors not used by any target tensor, sorted by name.
+fn unmapped_sources<'a>(
+    source: &'a StSource,
+    tensors: &[(String, TensorManifest)],
+) -> Vec<&'a str> {
+    let mut used = HashSet::new();
+    for (_, manifest) in tensors {
+        used.extend(manifest.source.as_deref());
+        used.extend(
+            manifest
+                .ops
+                .iter()
+                .flat_map(|op| op.sources())
+                .map(|s| s.as_str()),
+        );
+    }
 
-    let source_dimensions = TensorDimensions::from_width_last(&source_info.data_shape)?;
-    let scalars_len = tensor.dimensions.total();
+    let mut unmapped: Vec<_> = source.names().filter(|name| !used.contains(name)).collect();
+    unmapped.sort();
+    unmapped
+}
 
-    assert_eq!(source_info.data_type, "BF16");
-    assert_eq!(source_dimensions, tensor.dimensions);
+/// Compare captures by value, numerically if both are numbers.
+fn compare_captures(a: &[(String, String)], b: &[(String, String)]) -> Ordering {
+    for ((_, a), (_, b)) in a.iter().zip(b) {
+        let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        };
 
-    // Prepare buffer for the source data
-    let mut data = vec![0u8; scalars_len as usize * 2];
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
 
-    // Validate the source data is the correct size
-    let data_expected = source_info.data_offsets[1] - source_info.data_offsets[0];
-    assert_eq!(data.len(), data_expected as usize);
+    Ordering::Equal
+}
 
-    // Read the raw source data
-    let start = source_header.data_start + source_info.data_offsets[0];
-    source_file.seek(SeekFrom::Start(start))?;
-    source_file.read_exact(&mut data)?;
+/// Divide a tensor into chunks, so memory use is bounded regardless of tensor size.
+///
+/// Chunks contain whole blocks of the target type.
+/// Tensors with ops are transformed as a whole, in a single chunk.
+///
+/// Dimensions have already been validated against the source when processing.
+fn plan_chunks(
+    chunks: &mut Vec<ConvertChunk>,
+    source: &StSource,
+    index: usize,
+    tensor: &ConvertTensorInfo,
+) -> Result<(), Error> {
+    let (file, source_info) = source.tensor(&tensor.source)?;
+    let source_header = source.header(file);
+
+    let source_dtype = StDtype::parse(&source_info.data_type)
+        .with_context(|| format!("unable to read source tensor {:?}", tensor.source))?;
+
+    if !tensor.ops.is_empty() {
+        chunks.push(ConvertChunk {
+            tensor: index,
+            range: None,
+            first: true,
+        });
+        return Ok(());
+    }
 
-    // Convert to f32
-    // TODO: One at a time is inefficient
-    let mut scalars = vec![0f32; scalars_len as usize];
-    for (i, source_bytes) in data.chunks(2).enumerate() {
-        let half_value = half::bf16::from_le_bytes([source_bytes[0], source_bytes[1]]);
-        scalars[i] = half_value.to_f32();
+    // Validate the source data is the correct size
+    let data_len = tensor.info.dimensions.total() * source_dtype.size() as u64;
+    let data_expected = source_info
+        .data_size()
+        .with_context(|| format!("invalid source tensor {:?}", tensor.source))?;
+    if data_len != data_expected {
+        bail!(
+            "source tensor {:?} data size doesn't match its shape",
+            tensor.source
+        );
+    }
+
+    let block_size = tensor.info.tensor_type.block_size();
+    let chunk_scalars = (CHUNK_SCALARS / block_size).max(1) * block_size;
+
+    let mut start = source_header.data_start + source_info.data_offsets[0];
+    let mut remaining = tensor.info.dimensions.total();
+    let mut first = true;
+
+    // Empty tensors still need a chunk, to be padded and positioned
+    while remaining > 0 || first {
+        let scalars = remaining.min(chunk_scalars);
+
+        chunks.push(ConvertChunk {
+            tensor: index,
+            range: Some(SourceRange {
+                file,
+                dtype: source_dtype,
+                start,
+                scalars,
+            }),
+            first,
+        });
+
+        start += scalars * source_dtype.size() as u64;
+        remaining -= scalars;
+        first = false;
     }
 
-    Ok(scalars)
+    Ok(())
 }
 
-fn write_scalars(
-    target: &mut File,
-    data_start: u64,
+fn convert_chunk(
+    source: &mut StSource,
+    chunk: &ConvertChunk,
     tensor: &ConvertTensorInfo,
-    scalars: Vec<f32>,
-) -> Result<(), Error> {
-    // Convert to target format
-    let data = match tensor.ty {
-        TensorType::F16 => encode_values_f16(&scalars),
-        TensorType::F32 => encode_values_f32(&scalars),
-        _ => bail!("unexpected tensor type"),
+) -> Result<Vec<u8>, Error> {
+    let Some(range) = &chunk.range else {
+        return transform_tensor(source, tensor);
     };
 
-    // Pad if necessary
-    let position = write_padding(target)?;
+    let source_file = &mut source.file_mut(range.file).file;
 
-    // We should now be at the correct position, but make sure
-    assert_eq!(position, data_start + tensor.offset);
+    let mut data = vec![0u8; range.scalars as usize * range.dtype.size()];
+    source_file.seek(SeekFrom::Start(range.start))?;
+    source_file.read_exact(&mut data)?;
 
-    // Write the converted data
-    target.write_all(&data)?;
+    // The source may already be in the target format, then it's copied as-is
+    if range.dtype.tensor_type() == Some(tensor.info.tensor_type) {
+        return Ok(data);
+    }
 
-    Ok(())
+    let scalars = range.dtype.decode(&data);
+    encode_scalars(tensor, &scalars)
 }
 
-fn encode_values_f16(scalars: &[f32]) -> Vec<u8> {
-    // TODO: One at a time is inefficient
+fn transform_tensor(source: &mut StSource, tensor: &ConvertTensorInfo) -> Result<Vec<u8>, Error> {
+    let input = read_tensor(source, &tensor.source)?;
+    let output = ops::apply(&tensor.ops, input, |name| read_tensor(source, name))
+        .with_context(|| format!("failed to transform tensor {:?}", tensor.info.name))?;
 
-    let mut data = vec![0u8; scalars.len() * 2];
+    encode_scalars(tensor, &output.values)
+}
 
-    for (i, bytes) in data.chunks_mut(2).enumerate() {
-        let value = half::f16::from_f32(scalars[i]);
-        bytes.copy_from_slice(&value.to_le_bytes());
-    }
+fn read_tensor(source: &mut StSource, name: &str) -> Result<Tensor, Error> {
+    let values = source.read_values(name)?;
+    let shape = source.tensor(name)?.1.data_shape.clone();
 
-    data
+    Ok(Tensor { shape, values })
 }
 
-fn encode_values_f32(scalars: &[f32]) -> Vec<u8> {
-    // TODO: One at a time is inefficient
+fn encode_scalars(tensor: &ConvertTensorInfo, scalars: &[f32]) -> Result<Vec<u8>, Error> {
+    let data = match tensor.info.tensor_type {
+        TensorType::F16 => encode_f16(scalars),
+        TensorType::F32 => encode_f32(scalars),
+        _ => bail!("unexpected tensor type"),
+    };
+
+    Ok(data)
+}
+
+/// A part of a tensor to convert.
+struct ConvertChunk {
+    /// Index of the tensor in the task's tensors.
+    tensor: usize,
 
-    let mut data = vec![0u8; scalars.len() * 4];
+    /// Part of the source data to convert, or `None` to transform the whole tensor at once.
+    range: Option<SourceRange>,
 
-    for (i, bytes) in data.chunks_mut(4).enumerate() {
-        let value = scalars[i];
-        bytes.copy_from_slice(&value.to_le_bytes());
+    /// If this is the first chunk of the tensor.
+    first: bool,
+}
+
+impl ConvertChunk {
+    /// Approximate memory used to convert the chunk, the larger of its source data and its values
+    /// decoded to f32.
+    ///
+    /// Transformed tensors are converted at once, and cost their whole size.
+    fn cost(&self, tensor: &ConvertTensorInfo) -> u64 {
+        match &self.range {
+            Some(range) => range.scalars * range.dtype.size().max(4) as u64,
+            None => tensor.info.dimensions.total() * 4,
+        }
     }
+}
+
+struct SourceRange {
+    /// Index of the source file.
+    file: usize,
+
+    dtype: StDtype,
+
+    /// Start of the chunk's data in the source file.
+    start: u64,
 
-    data
+    scalars: u64,
 }
 
 pub struct ConvertTensorInfo {
-    pub name: String,
+    pub info: TensorInfo,
     pub source: String,
-    pub dimensions: TensorDimensions,
-    pub offset: u64,
-    pub ty: TensorType,
+    pub ops: Vec<TensorOp>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use gguf_swiss::{encode_f16, encode_f32, TensorDimensions, TensorType};
+    use toml::Table;
+
+    use crate::{
+        safetensors::tests::{write_source, write_test_source},
+        tasks::{
+            convert_safetensors::{unmapped_sources, ConvertSafetensorsTask, CHUNK_SCALARS},
+            PackTask, ProcessContext, WriteContext,
+        },
+    };
+
+    fn source_root(name: &str, tensors: &[(&str, &[u64])]) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("gguf-swiss-pack-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&root).unwrap();
+        write_test_source(&root.join("model.safetensors"), tensors);
+        root
+    }
+
+    fn rules_root(name: &str) -> PathBuf {
+        let tensors: &[(&str, &[u64])] = &[
+            ("rwkv.blocks.10.ln1.weight", &[4]),
+            ("rwkv.blocks.1.ln1.weight", &[4]),
+            ("rwkv.blocks.0.ln1.weight", &[4]),
+            ("rwkv.blocks.0.att.key.weight", &[2, 4]),
+            ("head.weight", &[2, 4]),
+        ];
+        source_root(name, tensors)
+    }
+
+    fn process(
+        root: &Path,
+        manifest: &str,
+    ) -> Result<(ConvertSafetensorsTask, ProcessContext), anyhow::Error> {
+        let manifest: Table = toml::from_str(manifest).unwrap();
+        let mut task = ConvertSafetensorsTask::new(&manifest)?;
+        let mut ctx = ProcessContext {
+            source_root: root.to_path_buf(),
+            metadata: Vec::new(),
+            tensors: Vec::new(),
+        };
+        task.process(&mut ctx)?;
+        Ok((task, ctx))
+    }
+
+    #[test]
+    fn convert_tensors() {
+        let root = source_root("convert", &[("a", &[2, 3]), ("b", &[4])]);
+        let (mut task, ctx) = process(
+            &root,
+            r#"
+            source = "model.safetensors"
+            tensors.x = { source = "a", type = "F32", dimensions = [3, 2] }
+            tensors.y = { source = "b", type = "F16" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(ctx.tensors[0].dimensions, TensorDimensions([3, 2, 0, 0]));
+        assert_eq!(ctx.tensors[1].offset, 32);
+
+        // Tensors are written from the source opened when processing
+        let path = root.join("output.gguf");
+        let mut output = std::fs::File::create(&path).unwrap();
+        task.write_tensors(&WriteContext { jobs: 2 }, &mut output)
+            .unwrap();
+        drop(output);
+
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(data.len(), 40);
+        assert_eq!(data[4..8], 1f32.to_le_bytes());
+        assert_eq!(data[34..36], [0x00, 0x3c]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn convert_chunked_tensors() {
+        let total = CHUNK_SCALARS + 100;
+        let root = source_root("chunked", &[("big", &[total]), ("small", &[4])]);
+        let (mut task, ctx) = process(
+            &root,
+            r#"
+            source = "model.safetensors"
+            tensors.a = { source = "big", type = "F16" }
+            tensors.b = { source = "big", type = "F32" }
+            tensors.c = { source = "small", type = "F32" }
+            "#,
+        )
+        .unwrap();
+
+        let path = root.join("output.gguf");
+        let mut output = std::fs::File::create(&path).unwrap();
+        task.write_tensors(&WriteContext { jobs: 3 }, &mut output)
+            .unwrap();
+        drop(output);
+
+        // Every chunk is converted and written in place, including the partial last chunk
+        let values: Vec<f32> = (0..total).map(|v| v as f32).collect();
+        let data = std::fs::read(&path).unwrap();
+        let tensor_data = |index: usize| {
+            let tensor = &ctx.tensors[index];
+            &data[tensor.offset as usize..(tensor.offset + tensor.size()) as usize]
+        };
+        assert_eq!(tensor_data(0), encode_f16(&values));
+        assert_eq!(tensor_data(1), encode_f32(&values));
+        assert_eq!(tensor_data(2), encode_f32(&[0.0, 1.0, 2.0, 3.0]));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn passthrough_matching_types() {
+        let root = source_root("passthrough", &[]);
+
+        // Signaling NaNs and subnormals, which change if decoded and encoded again
+        let half: Vec<u8> = [0x7c01u16, 0x0001, 0x8000, 0x3c00]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let single: Vec<u8> = [0x7f800001u32, 0x00000001]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let header = serde_json::json!({
+            "h": { "dtype": "F16", "shape": [4], "data_offsets": [0, 8] },
+            "f": { "dtype": "F32", "shape": [2], "data_offsets": [8, 16] },
+        });
+        write_source(
+            &root.join("model.safetensors"),
+            &header,
+            &[half.as_slice(), &single].concat(),
+        );
+
+        let (mut task, _) = process(
+            &root,
+            r#"
+            source = "model.safetensors"
+            tensors.h = { source = "h", type = "F16" }
+            tensors.f = { source = "f", type = "F32" }
+            "#,
+        )
+        .unwrap();
+
+        let path = root.join("output.gguf");
+        let mut output = std::fs::File::create(&path).unwrap();
+        task.write_tensors(&WriteContext { jobs: 1 }, &mut output)
+            .unwrap();
+        drop(output);
+
+        // Listed tensors are sorted by name
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(data[..8], single);
+        assert_eq!(data[32..40], half);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn dimension_mismatch() {
+        let root = source_root("mismatch", &[("a", &[2, 3])]);
+        let result = process(
+            &root,
+            r#"
+            source = "model.safetensors"
+            tensors.x = { source = "a", type = "F32", dimensions = [2, 3] }
+            "#,
+        );
+
+        let error = result.err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("has dimensions [2, 3] in the manifest"));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn expand_rules() {
+        let root = rules_root("rules");
+        let manifest = r#"
+            source = "model.safetensors"
+            tensors."blk.1.norm" = { source = "head.weight", type = "F32", dimensions = [4, 2] }
+            tensors."blk.10.norm" = { type = "F16" }
+
+            [[rules]]
+            source = "rwkv.blocks.{n}.ln1.weight"
+            target = "blk.{n}.norm"
+            type = "F32"
+            "#;
+        let (task, ctx) = process(&root, manifest).unwrap();
+
+        // Explicit tensors first, then rules sorted numerically, without explicit targets
+        let tensors: Vec<_> = ctx
+            .tensors
+            .iter()
+            .map(|t| (t.name.as_str(), t.tensor_type))
+            .collect();
+        assert_eq!(
+            tensors,
+            [
+                ("blk.1.norm", TensorType::F32),
+                ("blk.0.norm", TensorType::F32),
+                ("blk.10.norm", TensorType::F16),
+            ]
+        );
+        assert_eq!(task.tensors[0].source, "head.weight");
+
+        let source = task.source.as_ref().unwrap();
+        let expanded = task.expand_tensors(source).unwrap();
+        assert_eq!(
+            unmapped_sources(source, &expanded),
+            ["rwkv.blocks.0.att.key.weight", "rwkv.blocks.1.ln1.weight"]
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn invalid_rules() {
+        let root = rules_root("invalid-rules");
+        let error = |manifest: &str| format!("{:#}", process(&root, manifest).err().unwrap());
+
+        // Both rules map the same target
+        let duplicate = r#"
+            source = "model.safetensors"
+            rules = [
+                { source = "rwkv.blocks.{n}.ln1.weight", target = "blk.{n}", type = "F32" },
+                { source = "rwkv.blocks.{n}.att.key.weight", target = "blk.{n}", type = "F16" },
+            ]
+            "#;
+        assert!(error(duplicate).contains("multiple source tensors map to \"blk.0\""));
+
+        let unmatched = r#"
+            source = "model.safetensors"
+            tensors."blk.20.norm" = { type = "F16" }
+            rules = [{ source = "rwkv.blocks.{n}.ln1.weight", target = "blk.{n}.norm", type = "F32" }]
+            "#;
+        assert!(error(unmatched).contains("isn't mapped by a rule"));
+
+        let missing = r#"
+            source = "model.safetensors"
+            rules = [{ source = "rwkv.blocks.{n}.ln1.weight", target = "blk.{m}", type = "F32" }]
+            "#;
+        assert!(error(missing).contains("capture {m} of the target isn't in the source"));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
 }
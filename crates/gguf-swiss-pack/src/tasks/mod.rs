@@ -9,10 +9,12 @@ use gguf_swiss::{MetadataValue, TensorInfo};
 use toml::Table;
 
 use crate::tasks::{
-    add_model_card::AddModelCardTask, add_model_config::AddModelConfigTask,
-    convert_rwkv_tokenizer::ConvertRwkvTokenizerTask, convert_safetensors::ConvertSafetensorsTask,
+    add_hf_config::AddHfConfigTask, add_model_card::AddModelCardTask,
+    add_model_config::AddModelConfigTask, convert_rwkv_tokenizer::ConvertRwkvTokenizerTask,
+    convert_safetensors::ConvertSafetensorsTask,
 };
 
+mod add_hf_config;
 mod add_model_card;
 mod add_model_config;
 mod convert_rwkv_tokenizer;
@@ -41,6 +43,7 @@ fn load_task(key: &str, manifest: &Table) -> Result<TaskEntry, Error> {
     println!("loading task \"{}\" -> \"{}\"", key, name);
 
     let task: Box<dyn PackTask> = match name.as_str() {
+        "add-hf-config" => Box::new(AddHfConfigTask::new(manifest)?),
         "add-model-card" => Box::new(AddModelCardTask::new(manifest)?),
         "add-model-config" => Box::new(AddModelConfigTask::new(manifest)?),
         "convert-rwkv-tokenizer" => Box::new(ConvertRwkvTokenizerTask::new(manifest)?),
@@ -52,6 +55,7 @@ fn load_task(key: &str, manifest: &Table) -> Result<TaskEntry, Error> {
     Ok(value)
 }
 
+#[allow(clippy::type_complexity)]
 pub fn process(
     tasks: &mut [TaskEntry],
     source_root: PathBuf,
@@ -66,21 +70,22 @@ pub fn process(
 
     for entry in tasks {
         println!("processing \"{}\"", entry.name);
-        entry.task.process(&mut ctx)?;
+        entry
+            .task
+            .process(&mut ctx)
+            .with_context(|| format!("failed to process \"{}\"", entry.name))?;
     }
 
     Ok((ctx.metadata, ctx.tensors))
 }
 
-pub fn write_tensors(
-    tasks: &mut [TaskEntry],
-    source_root: &Path,
-    output: &mut File,
-) -> Result<(), Error> {
+pub fn write_tensors(tasks: &mut [TaskEntry], jobs: usize, output: &mut File) -> Result<(), Error> {
     println!("writing tensors");
 
+    let ctx = WriteContext { jobs };
+
     for entry in tasks {
-        entry.task.write_tensors(source_root, output)?;
+        entry.task.write_tensors(&ctx, output)?;
     }
 
     Ok(())
@@ -96,7 +101,7 @@ trait PackTask {
         Ok(())
     }
 
-    fn write_tensors(&mut self, _source_root: &Path, _output: &mut File) -> Result<(), Error> {
+    fn write_tensors(&mut self, _ctx: &WriteContext, _output: &mut File) -> Result<(), Error> {
         Ok(())
     }
 }
@@ -112,25 +117,74 @@ impl ProcessContext {
         &self.source_root
     }
 
-    fn push_metadata_str(&mut self, key: impl ToString, value: &str) {
+    fn push_metadata_str(&mut self, key: impl ToString, value: &str) -> Result<(), Error> {
         let value = value.as_bytes().to_vec();
-        self.push_metadata_value(key, MetadataValue::String(value));
+        self.push_metadata_value(key, MetadataValue::String(value))
     }
 
-    fn push_metadata_u32(&mut self, key: impl ToString, value: u32) {
-        self.push_metadata_value(key, MetadataValue::UInt32(value));
+    fn push_metadata_u32(&mut self, key: impl ToString, value: u32) -> Result<(), Error> {
+        self.push_metadata_value(key, MetadataValue::UInt32(value))
     }
 
     #[allow(dead_code)]
-    fn push_metadata_u64(&mut self, key: impl ToString, value: u64) {
-        self.push_metadata_value(key, MetadataValue::UInt64(value));
+    fn push_metadata_u64(&mut self, key: impl ToString, value: u64) -> Result<(), Error> {
+        self.push_metadata_value(key, MetadataValue::UInt64(value))
+    }
+
+    fn push_metadata_f32(&mut self, key: impl ToString, value: f32) -> Result<(), Error> {
+        self.push_metadata_value(key, MetadataValue::Float32(value))
     }
 
-    fn push_metadata_f32(&mut self, key: impl ToString, value: f32) {
-        self.push_metadata_value(key, MetadataValue::Float32(value));
+    /// Add a metadata value, failing if the key was already added, by this or another task.
+    fn push_metadata_value(
+        &mut self,
+        key: impl ToString,
+        value: MetadataValue,
+    ) -> Result<(), Error> {
+        let key = key.to_string();
+        if self.metadata.iter().any(|(k, _)| *k == key) {
+            bail!("metadata key {:?} is already set", key);
+        }
+
+        self.metadata.push((key, value));
+        Ok(())
     }
+}
+
+struct WriteContext {
+    /// Amount of worker threads to convert tensors with.
+    jobs: usize,
+}
+
+impl WriteContext {
+    fn jobs(&self) -> usize {
+        self.jobs
+    }
+}
 
-    fn push_metadata_value(&mut self, key: impl ToString, value: MetadataValue) {
-        self.metadata.push((key.to_string(), value));
+#[cfg(test)]
+mod tests {
+    use gguf_swiss::MetadataValue;
+
+    use crate::tasks::ProcessContext;
+
+    #[test]
+    fn duplicate_metadata() {
+        let mut ctx = ProcessContext {
+            source_root: Default::default(),
+            metadata: Vec::new(),
+            tensors: Vec::new(),
+        };
+
+        ctx.push_metadata_str("general.architecture", "llama")
+            .unwrap();
+        ctx.push_metadata_u32("llama.block_count", 24).unwrap();
+
+        // Keys set by another task are not silently duplicated
+        let error = ctx
+            .push_metadata_value("llama.block_count", MetadataValue::UInt64(24))
+            .unwrap_err();
+        assert!(error.to_string().contains("\"llama.block_count\""));
+        assert_eq!(ctx.metadata.len(), 2);
     }
 }
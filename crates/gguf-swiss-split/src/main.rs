@@ -0,0 +1,68 @@
+mod merge;
+mod paths;
+mod split;
+mod write;
+
+use anyhow::Error;
+use clap::{Parser, Subcommand};
+
+fn main() -> Result<(), Error> {
+    let args = Args::parse();
+
+    match args.command {
+        Command::Split {
+            model,
+            prefix,
+            max_size,
+            max_tensors,
+        } => split::split(&model, &prefix, max_size, max_tensors),
+        Command::Merge {
+            first_shard,
+            output,
+        } => merge::merge(&first_shard, &output),
+    }
+}
+
+/// GGUF Swiss Army Knife, model splitting utility.
+///
+/// Shards are compatible with llama.cpp's `gguf-split`, and named
+/// `{prefix}-00001-of-00003.gguf`.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Split a model into shards.
+    ///
+    /// The first shard contains all metadata, other shards only contain split metadata.
+    Split {
+        /// Path to the model to split.
+        model: String,
+
+        /// Prefix of the shard paths.
+        prefix: String,
+
+        /// Maximum size of tensor data in a shard, for example `500M` or `2G`.
+        ///
+        /// Tensors larger than the maximum are put in a shard of their own.
+        #[arg(long, value_name = "SIZE", value_parser = paths::parse_size, required_unless_present = "max_tensors")]
+        max_size: Option<u64>,
+
+        /// Maximum amount of tensors in a shard.
+        #[arg(long, value_name = "N")]
+        max_tensors: Option<usize>,
+    },
+
+    /// Merge shards into a single model.
+    Merge {
+        /// Path to the first shard, the other shards are found by name.
+        first_shard: String,
+
+        /// Path to write the merged model to.
+        output: String,
+    },
+}
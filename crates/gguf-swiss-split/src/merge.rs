@@ -0,0 +1,34 @@
+use std::{fs::File, path::Path};
+
+use anyhow::{bail, Context, Error};
+use gguf_swiss::SplitModel;
+
+use crate::{
+    paths::{same_file, shard_paths},
+    write::write_model,
+};
+
+pub fn merge(first_shard: &str, output: &str) -> Result<(), Error> {
+    let paths = shard_paths(Path::new(first_shard))?;
+    let output = Path::new(output);
+
+    // The output would be truncated before the shard is read
+    if paths.iter().any(|path| same_file(path, output)) {
+        bail!("output {} is one of the shards", output.display());
+    }
+
+    let mut files = Vec::new();
+    for path in &paths {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        files.push(file);
+    }
+
+    let mut model = SplitModel::open(files).context("failed to read shards")?;
+    println!("merging {} shards", model.shard_count());
+
+    let mut header = model.header.clone();
+    write_model(output, &mut header, |i| model.read_tensor_data(i))?;
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Error};
+
+/// Path of a shard, with a 0-based index.
+pub fn shard_path(prefix: &str, index: usize, count: usize) -> PathBuf {
+    format!("{}-{:05}-of-{:05}.gguf", prefix, index + 1, count).into()
+}
+
+/// Find the paths of all shards from the path of the first shard.
+pub fn shard_paths(first: &Path) -> Result<Vec<PathBuf>, Error> {
+    let name = first.to_str().context("invalid shard path")?;
+    let Some((prefix, count)) = parse_shard_path(name) else {
+        bail!("shard path doesn't end in \"-00001-of-NNNNN.gguf\"");
+    };
+
+    let paths = (0..count).map(|i| shard_path(prefix, i, count)).collect();
+    Ok(paths)
+}
+
+/// Parse the prefix and shard count from the path of the first shard.
+fn parse_shard_path(path: &str) -> Option<(&str, usize)> {
+    let rest = path.strip_suffix(".gguf")?;
+    let (rest, count) = rest.rsplit_once("-of-")?;
+    let prefix = rest.strip_suffix("-00001")?;
+
+    Some((prefix, count.parse().ok()?))
+}
+
+/// Check if two paths are the same existing file.
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Parse a size in bytes, with an optional `K`, `M`, or `G` suffix for powers of 1000.
+pub fn parse_size(value: &str) -> Result<u64, Error> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, 'K')) => (&value[..i], 1000),
+        Some((i, 'M')) => (&value[..i], 1000 * 1000),
+        Some((i, 'G')) => (&value[..i], 1000 * 1000 * 1000),
+        _ => (value, 1),
+    };
+
+    let number: u64 = number.parse().context("invalid size")?;
+    Ok(number * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paths::{parse_shard_path, parse_size, same_file, shard_path};
+
+    #[test]
+    fn shard_paths() {
+        let path = shard_path("out/model", 0, 3);
+        assert_eq!(path.to_str(), Some("out/model-00001-of-00003.gguf"));
+        assert_eq!(
+            parse_shard_path("out/model-00001-of-00003.gguf"),
+            Some(("out/model", 3))
+        );
+        assert_eq!(parse_shard_path("out/model-00002-of-00003.gguf"), None);
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("2G").unwrap(), 2_000_000_000);
+        assert!(parse_size("G").is_err());
+    }
+
+    #[test]
+    fn same_files() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("gguf-swiss-split-{}-same", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+
+        let other = dir.join(".").join(path.file_name().unwrap());
+        assert!(same_file(&path, &other));
+        assert!(!same_file(&path, &dir));
+        assert!(!same_file(&path, &path.with_extension("missing")));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
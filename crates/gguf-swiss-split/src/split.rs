@@ -0,0 +1,102 @@
+use std::{fs::File, ops::Range};
+
+use anyhow::{bail, Context, Error};
+use gguf_swiss::{Header, SplitModel, TensorInfo};
+
+use crate::{paths::shard_path, write::write_model};
+
+pub fn split(
+    model: &str,
+    prefix: &str,
+    max_size: Option<u64>,
+    max_tensors: Option<usize>,
+) -> Result<(), Error> {
+    let file = File::open(model)?;
+    let mut model = SplitModel::open(vec![file]).context("failed to read model")?;
+
+    let shards = plan_shards(&model.header.tensors, max_size, max_tensors);
+    if shards.len() > u16::MAX as usize {
+        bail!("too many shards");
+    }
+
+    let count = shards.len();
+    let tensors_count = model.header.tensors.len() as i32;
+
+    for (index, range) in shards.into_iter().enumerate() {
+        // Only the first shard contains the model's metadata
+        let mut metadata = gguf_swiss::split_metadata(index as u16, count as u16, tensors_count);
+        if index == 0 {
+            metadata.extend(model.header.metadata.iter().cloned());
+        }
+
+        let mut header = Header {
+            metadata,
+            tensors: model.header.tensors[range.clone()].to_vec(),
+        };
+        let path = shard_path(prefix, index, count);
+        write_model(&path, &mut header, |i| {
+            model.read_tensor_data(range.start + i)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Divide tensors over shards, as ranges of tensor indices.
+///
+/// Every shard contains at least one tensor.
+fn plan_shards(
+    tensors: &[TensorInfo],
+    max_size: Option<u64>,
+    max_tensors: Option<usize>,
+) -> Vec<Range<usize>> {
+    let mut shards = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+
+    for (index, tensor) in tensors.iter().enumerate() {
+        let over_size = max_size.is_some_and(|max| size + tensor.size() > max);
+        let over_count = max_tensors.is_some_and(|max| index - start >= max);
+
+        if index != start && (over_size || over_count) {
+            shards.push(start..index);
+            start = index;
+            size = 0;
+        }
+
+        size += tensor.size();
+    }
+
+    if start != tensors.len() || shards.is_empty() {
+        shards.push(start..tensors.len());
+    }
+
+    shards
+}
+
+#[cfg(test)]
+mod tests {
+    use gguf_swiss::{TensorDimensions, TensorInfo, TensorType};
+
+    use crate::split::plan_shards;
+
+    #[test]
+    fn plan_shards_by_size_and_count() {
+        let tensors: Vec<_> = [4, 4, 12, 2, 2, 2]
+            .iter()
+            .map(|size| TensorInfo {
+                name: String::new(),
+                tensor_type: TensorType::I8,
+                dimensions: TensorDimensions::from_width_last(&[*size]).unwrap(),
+                offset: 0,
+            })
+            .collect();
+
+        assert_eq!(plan_shards(&tensors, Some(8), None), vec![0..2, 2..3, 3..6]);
+        assert_eq!(plan_shards(&tensors, None, Some(4)), vec![0..4, 4..6]);
+        assert_eq!(
+            plan_shards(&tensors, Some(8), Some(2)),
+            vec![0..2, 2..3, 3..5, 5..6]
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Error;
+use gguf_swiss::{write_padding, write_tensor_data, Header};
+
+/// Write a model, reading the data of every tensor with `read_tensor`.
+///
+/// Tensor offsets are recalculated.
+pub fn write_model(
+    path: &Path,
+    header: &mut Header,
+    mut read_tensor: impl FnMut(usize) -> Result<Vec<u8>, Error>,
+) -> Result<(), Error> {
+    header.update_offsets();
+
+    println!("writing {}", path.display());
+    let mut target = BufWriter::new(File::create(path)?);
+
+    gguf_swiss::write_header(&mut target, header)?;
+    let data_start = write_padding(&mut target)?;
+
+    for (index, tensor) in header.tensors.iter().enumerate() {
+        let data = read_tensor(index)?;
+
+        write_tensor_data(&mut target, data_start, tensor, &data)?;
+    }
+
+    write_padding(&mut target)?;
+    target.flush()?;
+    Ok(())
+}
@@ -0,0 +1,65 @@
+//! Throughput of the bulk float conversions, compared to converting one value at a time.
+//!
+//! Run with `cargo bench -p gguf-swiss`.
+
+use std::{hint::black_box, time::Instant};
+
+use half::{bf16, f16};
+
+const VALUES: usize = 16 * 1024 * 1024;
+const ITERATIONS: u32 = 5;
+
+fn main() {
+    let values: Vec<f32> = (0..VALUES)
+        .map(|i| (i % 4096) as f32 * 0.001 - 2.0)
+        .collect();
+    let f16_data = gguf_swiss::encode_f16(&values);
+    let bf16_data = gguf_swiss::encode_bf16(&values);
+    let f32_data = gguf_swiss::encode_f32(&values);
+
+    bench("encode_f16", || gguf_swiss::encode_f16(&values));
+    bench("encode_f16 (scalar)", || {
+        values
+            .iter()
+            .flat_map(|v| f16::from_f32(*v).to_le_bytes())
+            .collect::<Vec<u8>>()
+    });
+    bench("encode_bf16", || gguf_swiss::encode_bf16(&values));
+    bench("encode_bf16 (scalar)", || {
+        values
+            .iter()
+            .flat_map(|v| bf16::from_f32(*v).to_le_bytes())
+            .collect::<Vec<u8>>()
+    });
+    bench("encode_f32", || gguf_swiss::encode_f32(&values));
+
+    bench("decode_f16", || gguf_swiss::decode_f16(&f16_data));
+    bench("decode_f16 (scalar)", || {
+        f16_data
+            .chunks_exact(2)
+            .map(|b| f16::from_le_bytes([b[0], b[1]]).to_f32())
+            .collect::<Vec<f32>>()
+    });
+    bench("decode_bf16", || gguf_swiss::decode_bf16(&bf16_data));
+    bench("decode_bf16 (scalar)", || {
+        bf16_data
+            .chunks_exact(2)
+            .map(|b| bf16::from_le_bytes([b[0], b[1]]).to_f32())
+            .collect::<Vec<f32>>()
+    });
+    bench("decode_f32", || gguf_swiss::decode_f32(&f32_data));
+}
+
+fn bench<T>(name: &str, mut f: impl FnMut() -> T) {
+    // Warm up, so the first run doesn't pay for page faults
+    black_box(f());
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+
+    let rate = VALUES as f64 / elapsed.as_secs_f64() / 1_000_000.0;
+    println!("{:<24} {:>10.2?} {:>10.0} M values/s", name, elapsed, rate);
+}
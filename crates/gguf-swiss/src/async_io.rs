@@ -0,0 +1,271 @@
+//! Async counterparts of the header reader and writer, over tokio's IO traits.
+//!
+//! These share the parsing and encoding logic with the sync path, buffering the header in memory.
+
+use anyhow::{bail, Context, Error};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{read::limits, read_header, write_header, Header, MetadataType, MAGIC_NUMBER};
+
+/// Read the header of a GGUF file async reader.
+///
+/// The header is copied into memory by following its structure, reading exactly the bytes it
+/// consists of, and then parsed once.
+/// The same limits as `read_header` are checked while copying, before reading any data.
+/// Like `read_header`, this leaves the reader positioned directly after the header.
+/// This does many small reads, wrap the reader in a `tokio::io::BufReader` if it's unbuffered.
+pub async fn read_header_async<R>(reader: &mut R) -> Result<Header, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut copy = HeaderCopy {
+        reader,
+        data: Vec::new(),
+    };
+
+    // Magic number, version, tensor count and metadata count
+    copy.copy(24).await?;
+    if copy.data[0..4] != MAGIC_NUMBER {
+        bail!("magic number doesn't match");
+    }
+    limits::check_version(u32::from_le_bytes(copy.data[4..8].try_into()?))?;
+    let tensor_count = u64::from_le_bytes(copy.data[8..16].try_into()?);
+    let metadata_kv_count = u64::from_le_bytes(copy.data[16..24].try_into()?);
+    limits::check_counts(tensor_count, metadata_kv_count)?;
+
+    for _ in 0..metadata_kv_count {
+        copy.copy_string().await?;
+        let ty = copy.copy_u32().await?;
+        copy.copy_value(ty).await?;
+    }
+
+    for _ in 0..tensor_count {
+        copy.copy_string().await?;
+        let dimensions_count = copy.copy_u32().await?;
+        limits::check_dimensions_count(dimensions_count)?;
+
+        // Dimensions, type and offset
+        copy.copy(dimensions_count as u64 * 8 + 12).await?;
+    }
+
+    read_header(&mut copy.data.as_slice())
+}
+
+/// Copies the bytes of a header from a reader into memory.
+struct HeaderCopy<'a, R> {
+    reader: &'a mut R,
+    data: Vec<u8>,
+}
+
+impl<R> HeaderCopy<'_, R>
+where
+    R: AsyncRead + Unpin,
+{
+    async fn copy(&mut self, length: u64) -> Result<(), Error> {
+        // Not reading into a buffer of the full length up front, as it isn't validated yet
+        let read = (&mut *self.reader)
+            .take(length)
+            .read_to_end(&mut self.data)
+            .await?;
+
+        if read as u64 != length {
+            bail!("unexpected end of file while reading header");
+        }
+
+        Ok(())
+    }
+
+    async fn copy_u32(&mut self) -> Result<u32, Error> {
+        self.copy(4).await?;
+        let bytes = self.data[self.data.len() - 4..].try_into()?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    async fn copy_u64(&mut self) -> Result<u64, Error> {
+        self.copy(8).await?;
+        let bytes = self.data[self.data.len() - 8..].try_into()?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    async fn copy_string(&mut self) -> Result<(), Error> {
+        let length = self.copy_u64().await?;
+        limits::check_string_length(length)?;
+        self.copy(length).await
+    }
+
+    async fn copy_value(&mut self, ty: u32) -> Result<(), Error> {
+        // Nested arrays are followed using a stack of element types, remaining counts, and the
+        // array depth of the elements
+        let mut pending = vec![(ty, 1, 0)];
+
+        while let Some((ty, count, depth)) = pending.pop() {
+            if count == 0 {
+                continue;
+            }
+
+            let ty = MetadataType::from_u32(ty).context("invalid type")?;
+            match ty {
+                MetadataType::String => {
+                    for _ in 0..count {
+                        self.copy_string().await?;
+                    }
+                }
+                MetadataType::Array => {
+                    pending.push((ty as u32, count - 1, depth));
+                    let element_type = self.copy_u32().await?;
+                    let length = self.copy_u64().await?;
+                    limits::check_array(depth, length)?;
+                    pending.push((element_type, length, depth + 1));
+                }
+                ty => {
+                    let length = count
+                        .checked_mul(value_size(ty))
+                        .context("excessive array size")?;
+                    self.copy(length).await?
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Size in bytes of a fixed-size metadata value.
+fn value_size(ty: MetadataType) -> u64 {
+    match ty {
+        MetadataType::UInt8 | MetadataType::Int8 | MetadataType::Bool => 1,
+        MetadataType::UInt16 | MetadataType::Int16 => 2,
+        MetadataType::UInt32 | MetadataType::Int32 | MetadataType::Float32 => 4,
+        MetadataType::UInt64 | MetadataType::Int64 | MetadataType::Float64 => 8,
+        MetadataType::String | MetadataType::Array => 0,
+    }
+}
+
+/// Write the header of a GGUF file to an async writer.
+pub async fn write_header_async<W>(writer: &mut W, header: &Header) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = Vec::new();
+    write_header(&mut buffer, header)?;
+
+    writer.write_all(&buffer).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, BufReader};
+
+    use crate::{
+        read_header_async, write_header_async, Header, MetadataArray, MetadataType, MetadataValue,
+        TensorDimensions, TensorInfo, TensorType,
+    };
+
+    fn test_header() -> Header {
+        let tokens = (0..100)
+            .map(|i| format!("token {}", i).into_bytes())
+            .collect();
+
+        Header {
+            metadata: vec![
+                (
+                    "general.name".to_string(),
+                    MetadataValue::String(b"test".to_vec()),
+                ),
+                (
+                    "tokenizer.ggml.tokens".to_string(),
+                    MetadataValue::Array(MetadataArray::String(tokens)),
+                ),
+                (
+                    "tokenizer.ggml.token_type".to_string(),
+                    MetadataValue::Array(MetadataArray::UInt32(vec![1; 100])),
+                ),
+                ("test.eps".to_string(), MetadataValue::Float64(1e-5)),
+            ],
+            tensors: vec![TensorInfo {
+                name: "output.weight".to_string(),
+                tensor_type: TensorType::F16,
+                dimensions: TensorDimensions([16, 8, 0, 0]),
+                offset: 0,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn async_header_roundtrip() {
+        let header = test_header();
+
+        let mut data = Vec::new();
+        write_header_async(&mut data, &header).await.unwrap();
+        data.extend_from_slice(b"tensor data");
+
+        // Both unbuffered, and with a tiny buffer splitting the header over many chunks
+        for capacity in [None, Some(7)] {
+            let mut reader: Box<dyn tokio::io::AsyncRead + Unpin> = match capacity {
+                Some(capacity) => Box::new(BufReader::with_capacity(capacity, data.as_slice())),
+                None => Box::new(data.as_slice()),
+            };
+            let result = read_header_async(&mut reader).await.unwrap();
+
+            assert_eq!(result.metadata, header.metadata);
+            assert_eq!(result.tensors[0].name, "output.weight");
+            assert_eq!(
+                result.tensors[0].dimensions,
+                TensorDimensions([16, 8, 0, 0])
+            );
+
+            // The reader should be left directly after the header
+            let mut remainder = Vec::new();
+            reader.read_to_end(&mut remainder).await.unwrap();
+            assert_eq!(remainder, b"tensor data");
+        }
+    }
+
+    #[tokio::test]
+    async fn async_header_truncated() {
+        let mut data = Vec::new();
+        write_header_async(&mut data, &test_header()).await.unwrap();
+        data.truncate(data.len() - 3);
+
+        assert!(read_header_async(&mut data.as_slice()).await.is_err());
+        assert!(read_header_async(&mut &b"NOPE"[..]).await.is_err());
+    }
+
+    /// Header of a single metadata entry, up to its value.
+    fn entry_header(ty: u32) -> Vec<u8> {
+        let mut data = b"GGUF".to_vec();
+        data.extend(3u32.to_le_bytes());
+        data.extend(0u64.to_le_bytes());
+        data.extend(1u64.to_le_bytes());
+        data.extend(1u64.to_le_bytes());
+        data.push(b'a');
+        data.extend(ty.to_le_bytes());
+        data
+    }
+
+    #[tokio::test]
+    async fn async_header_limits() {
+        // Rejected before trying to read the data the lengths claim
+        let mut array = entry_header(MetadataType::Array as u32);
+        array.extend((MetadataType::UInt64 as u32).to_le_bytes());
+        array.extend(u64::MAX.to_le_bytes());
+        let error = read_header_async(&mut array.as_slice()).await.unwrap_err();
+        assert_eq!(error.to_string(), "excessive array size");
+
+        let mut string = entry_header(MetadataType::String as u32);
+        string.extend(u64::MAX.to_le_bytes());
+        let error = read_header_async(&mut string.as_slice()).await.unwrap_err();
+        assert_eq!(error.to_string(), "invalid string: too long");
+
+        let mut version = entry_header(MetadataType::String as u32);
+        version[4..8].copy_from_slice(&1u32.to_le_bytes());
+        assert!(read_header_async(&mut version.as_slice()).await.is_err());
+
+        let mut count = entry_header(MetadataType::String as u32);
+        count[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        let error = read_header_async(&mut count.as_slice()).await.unwrap_err();
+        assert_eq!(error.to_string(), "excessive tensor count");
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::{bail, Error};
+
+use crate::{dequantize, encode_f16, encode_f32, TensorType};
+
+/// Convert raw tensor data from one type to another.
+///
+/// Any type supported by `dequantize` can be converted to `F32` or `F16`.
+/// Data of the same type is returned as-is.
+pub fn convert_tensor_data(
+    from: TensorType,
+    to: TensorType,
+    data: &[u8],
+) -> Result<Vec<u8>, Error> {
+    if from == to {
+        return Ok(data.to_vec());
+    }
+
+    let values = match to {
+        TensorType::F32 | TensorType::F16 => dequantize(from, data)?,
+        _ => bail!("converting {:?} to {:?} is not supported", from, to),
+    };
+
+    let data = match to {
+        TensorType::F32 => encode_f32(&values),
+        _ => encode_f16(&values),
+    };
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{convert_tensor_data, TensorType};
+
+    #[test]
+    fn convert_f32_f16() {
+        let data: Vec<u8> = [1.0f32, -0.5]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+
+        let half = convert_tensor_data(TensorType::F32, TensorType::F16, &data).unwrap();
+        assert_eq!(half, vec![0x00, 0x3c, 0x00, 0xb8]);
+
+        let back = convert_tensor_data(TensorType::F16, TensorType::F32, &half).unwrap();
+        assert_eq!(back, data);
+
+        assert!(convert_tensor_data(TensorType::F32, TensorType::Q8_0, &data).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::{bail, Error};
+use half::f16;
+
+use crate::{decode_f16, decode_f32, TensorType};
+
+/// Decode raw tensor data of a given type to f32 values.
+///
+/// Quantized types are dequantized block by block, following ggml's reference implementation.
+pub fn dequantize(tensor_type: TensorType, data: &[u8]) -> Result<Vec<f32>, Error> {
+    let type_size = tensor_type.type_size() as usize;
+    let block_size = tensor_type.block_size() as usize;
+
+    if type_size == 0 || !data.len().is_multiple_of(type_size) {
+        bail!("data size doesn't match tensor type {:?}", tensor_type);
+    }
+
+    // Plain floats are converted in bulk, rather than block by block
+    match tensor_type {
+        TensorType::F32 => return Ok(decode_f32(data)),
+        TensorType::F16 => return Ok(decode_f16(data)),
+        _ => {}
+    }
+
+    let blocks = data.chunks_exact(type_size);
+    let mut values = Vec::with_capacity(blocks.len() * block_size);
+
+    let dequantize_block: fn(&[u8], &mut Vec<f32>) = match tensor_type {
+        TensorType::I8 => |b, v| v.push(b[0] as i8 as f32),
+        TensorType::I16 => |b, v| v.push(i16::from_le_bytes([b[0], b[1]]) as f32),
+        TensorType::I32 => |b, v| v.push(i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32),
+        TensorType::Q4_0 => dequantize_q4_0,
+        TensorType::Q4_1 => dequantize_q4_1,
+        TensorType::Q5_0 => dequantize_q5_0,
+        TensorType::Q5_1 => dequantize_q5_1,
+        TensorType::Q8_0 => |b, v| dequantize_q8(read_f16(b, 0), &b[2..], v),
+        TensorType::Q8_1 => |b, v| dequantize_q8(read_f16(b, 0), &b[4..], v),
+        TensorType::Q2_K => dequantize_q2_k,
+        TensorType::Q3_K => dequantize_q3_k,
+        TensorType::Q4_K => dequantize_q4_k,
+        TensorType::Q5_K => dequantize_q5_k,
+        TensorType::Q6_K => dequantize_q6_k,
+        TensorType::Q8_K => |b, v| {
+            let d = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+            dequantize_q8(d, &b[4..260], v)
+        },
+        ty => bail!("dequantizing {:?} is not supported", ty),
+    };
+
+    for block in blocks {
+        dequantize_block(block, &mut values);
+    }
+
+    Ok(values)
+}
+
+fn read_f16(data: &[u8], offset: usize) -> f32 {
+    f16::from_le_bytes([data[offset], data[offset + 1]]).to_f32()
+}
+
+fn dequantize_q4_0(block: &[u8], values: &mut Vec<f32>) {
+    let d = read_f16(block, 0);
+    let qs = &block[2..18];
+
+    values.extend(qs.iter().map(|q| ((q & 0xF) as i32 - 8) as f32 * d));
+    values.extend(qs.iter().map(|q| ((q >> 4) as i32 - 8) as f32 * d));
+}
+
+fn dequantize_q4_1(block: &[u8], values: &mut Vec<f32>) {
+    let d = read_f16(block, 0);
+    let m = read_f16(block, 2);
+    let qs = &block[4..20];
+
+    values.extend(qs.iter().map(|q| (q & 0xF) as f32 * d + m));
+    values.extend(qs.iter().map(|q| (q >> 4) as f32 * d + m));
+}
+
+fn dequantize_q5_0(block: &[u8], values: &mut Vec<f32>) {
+    let d = read_f16(block, 0);
+    let qh = u32::from_le_bytes([block[2], block[3], block[4], block[5]]);
+    let qs = &block[6..22];
+
+    let low = qs.iter().enumerate().map(|(j, q)| {
+        let high = ((qh >> j) << 4) & 0x10;
+        ((q & 0xF) as i32 | high as i32) - 16
+    });
+    values.extend(low.map(|x| x as f32 * d));
+
+    let high = qs.iter().enumerate().map(|(j, q)| {
+        let high = (qh >> (j + 12)) & 0x10;
+        ((q >> 4) as i32 | high as i32) - 16
+    });
+    values.extend(high.map(|x| x as f32 * d));
+}
+
+fn dequantize_q5_1(block: &[u8], values: &mut Vec<f32>) {
+    let d = read_f16(block, 0);
+    let m = read_f16(block, 2);
+    let qh = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let qs = &block[8..24];
+
+    let low = qs.iter().enumerate().map(|(j, q)| {
+        let high = ((qh >> j) << 4) & 0x10;
+        (q & 0xF) as u32 | high
+    });
+    values.extend(low.map(|x| x as f32 * d + m));
+
+    let high = qs.iter().enumerate().map(|(j, q)| {
+        let high = (qh >> (j + 12)) & 0x10;
+        (q >> 4) as u32 | high
+    });
+    values.extend(high.map(|x| x as f32 * d + m));
+}
+
+fn dequantize_q8(d: f32, qs: &[u8], values: &mut Vec<f32>) {
+    values.extend(qs.iter().map(|q| *q as i8 as f32 * d));
+}
+
+fn dequantize_q2_k(block: &[u8], values: &mut Vec<f32>) {
+    let scales = &block[0..16];
+    let qs = &block[16..80];
+    let d = read_f16(block, 80);
+    let dmin = read_f16(block, 82);
+
+    // Every 32 bytes of quants hold 4 groups of 32 2-bit values, each group with 2 scales
+    let mut scales = scales.iter();
+    for q in qs.chunks_exact(32) {
+        for shift in [0, 2, 4, 6] {
+            for half in q.chunks_exact(16) {
+                let sc = scales.next().unwrap();
+                let dl = d * (sc & 0xF) as f32;
+                let ml = dmin * (sc >> 4) as f32;
+
+                values.extend(half.iter().map(|q| dl * ((q >> shift) & 3) as f32 - ml));
+            }
+        }
+    }
+}
+
+fn dequantize_q3_k(block: &[u8], values: &mut Vec<f32>) {
+    let hmask = &block[0..32];
+    let qs = &block[32..96];
+    let d = read_f16(block, 108);
+
+    // Unpack the 16 6-bit scales, low 4 bits are in the first 8 bytes, high 2 bits in the last 4
+    let aux = |i: usize| u32::from_le_bytes(block[96 + i * 4..100 + i * 4].try_into().unwrap());
+    let (a0, a1, tmp) = (aux(0), aux(1), aux(2));
+    let (kmask1, kmask2) = (0x03030303, 0x0f0f0f0f);
+    let packed = [
+        (a0 & kmask2) | ((tmp & kmask1) << 4),
+        (a1 & kmask2) | (((tmp >> 2) & kmask1) << 4),
+        ((a0 >> 4) & kmask2) | (((tmp >> 4) & kmask1) << 4),
+        ((a1 >> 4) & kmask2) | (((tmp >> 6) & kmask1) << 4),
+    ];
+    let mut scales = packed
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .map(|sc| sc as i8 as i32 - 32);
+
+    // Like Q2_K, with the high bit of every value in the mask, subtracting 4 if not set
+    let mut m = 1;
+    for q in qs.chunks_exact(32) {
+        for shift in [0, 2, 4, 6] {
+            for (half, hm) in q.chunks_exact(16).zip(hmask.chunks_exact(16)) {
+                let dl = d * scales.next().unwrap() as f32;
+
+                let quants = half.iter().zip(hm).map(|(q, h)| {
+                    let high = if h & m != 0 { 0 } else { 4 };
+                    ((q >> shift) & 3) as i32 - high
+                });
+                values.extend(quants.map(|q| dl * q as f32));
+            }
+            m <<= 1;
+        }
+    }
+}
+
+/// Unpack the 6-bit scale and min of sub-block `j` used by `Q4_K` and `Q5_K`.
+fn scale_min_k4(j: usize, q: &[u8]) -> (f32, f32) {
+    let (scale, min) = if j < 4 {
+        (q[j] & 63, q[j + 4] & 63)
+    } else {
+        (
+            (q[j + 4] & 0xF) | ((q[j - 4] >> 6) << 4),
+            (q[j + 4] >> 4) | ((q[j] >> 6) << 4),
+        )
+    };
+
+    (scale as f32, min as f32)
+}
+
+fn dequantize_q4_k(block: &[u8], values: &mut Vec<f32>) {
+    let d = read_f16(block, 0);
+    let dmin = read_f16(block, 2);
+    let scales = &block[4..16];
+    let qs = &block[16..144];
+
+    for (i, q) in qs.chunks_exact(32).enumerate() {
+        let (sc1, m1) = scale_min_k4(i * 2, scales);
+        let (sc2, m2) = scale_min_k4(i * 2 + 1, scales);
+
+        values.extend(q.iter().map(|q| d * sc1 * (q & 0xF) as f32 - dmin * m1));
+        values.extend(q.iter().map(|q| d * sc2 * (q >> 4) as f32 - dmin * m2));
+    }
+}
+
+fn dequantize_q5_k(block: &[u8], values: &mut Vec<f32>) {
+    let d = read_f16(block, 0);
+    let dmin = read_f16(block, 2);
+    let scales = &block[4..16];
+    let qh = &block[16..48];
+    let qs = &block[48..176];
+
+    for (i, ql) in qs.chunks_exact(32).enumerate() {
+        let (sc1, m1) = scale_min_k4(i * 2, scales);
+        let (sc2, m2) = scale_min_k4(i * 2 + 1, scales);
+        let u1 = 1 << (i * 2);
+        let u2 = 2 << (i * 2);
+
+        let low = ql
+            .iter()
+            .zip(qh)
+            .map(|(l, h)| (l & 0xF) + (h & u1 != 0) as u8 * 16);
+        values.extend(low.map(|q| d * sc1 * q as f32 - dmin * m1));
+
+        let high = ql
+            .iter()
+            .zip(qh)
+            .map(|(l, h)| (l >> 4) + (h & u2 != 0) as u8 * 16);
+        values.extend(high.map(|q| d * sc2 * q as f32 - dmin * m2));
+    }
+}
+
+fn dequantize_q6_k(block: &[u8], values: &mut Vec<f32>) {
+    let ql = &block[0..128];
+    let qh = &block[128..192];
+    let scales = &block[192..208];
+    let d = read_f16(block, 208);
+
+    for n in 0..2 {
+        let ql = &ql[n * 64..];
+        let qh = &qh[n * 32..];
+        let sc = |i: usize| scales[n * 8 + i] as i8 as f32;
+
+        let mut out = [0f32; 128];
+        for l in 0..32 {
+            let is = l / 16;
+            let q1 = ((ql[l] & 0xF) | ((qh[l] & 3) << 4)) as i32 - 32;
+            let q2 = ((ql[l + 32] & 0xF) | (((qh[l] >> 2) & 3) << 4)) as i32 - 32;
+            let q3 = ((ql[l] >> 4) | (((qh[l] >> 4) & 3) << 4)) as i32 - 32;
+            let q4 = ((ql[l + 32] >> 4) | (((qh[l] >> 6) & 3) << 4)) as i32 - 32;
+
+            out[l] = d * sc(is) * q1 as f32;
+            out[l + 32] = d * sc(is + 2) * q2 as f32;
+            out[l + 64] = d * sc(is + 4) * q3 as f32;
+            out[l + 96] = d * sc(is + 6) * q4 as f32;
+        }
+        values.extend_from_slice(&out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use half::f16;
+
+    use crate::{dequantize, TensorType};
+
+    #[test]
+    fn dequantize_q8_0() {
+        let mut block = f16::from_f32(0.5).to_le_bytes().to_vec();
+        block.extend((0..32).map(|i| (i as i8 - 16) as u8));
+
+        let values = dequantize(TensorType::Q8_0, &block).unwrap();
+        assert_eq!(values.len(), 32);
+        assert_eq!(values[0], -8.0);
+        assert_eq!(values[31], 7.5);
+    }
+
+    #[test]
+    fn dequantize_q2_k() {
+        // Scale 1 and min 2, with every byte of quants holding 0, 1, 2 and 3
+        let mut block = vec![0x21; 16];
+        block.extend([0b11100100; 64]);
+        block.extend(f16::from_f32(0.5).to_le_bytes());
+        block.extend(f16::from_f32(0.25).to_le_bytes());
+
+        let values = dequantize(TensorType::Q2_K, &block).unwrap();
+        assert_eq!(values.len(), 256);
+        assert_eq!(values[0], -0.5);
+        assert_eq!(values[32], 0.0);
+        assert_eq!(values[64], 0.5);
+        assert_eq!(values[127], 1.0);
+        assert_eq!(values[128], -0.5);
+    }
+
+    #[test]
+    fn dequantize_q3_k() {
+        // No high bits set in the first half, all set in the second
+        let mut block = vec![0xF0; 32];
+        block.extend([0b11100100; 64]);
+
+        // Scales of 33 (1 after subtracting 32), low 4 bits of 1, high 2 bits of 2
+        block.extend([0x11; 8]);
+        block.extend([0b10101010; 4]);
+        block.extend(f16::from_f32(0.5).to_le_bytes());
+
+        let values = dequantize(TensorType::Q3_K, &block).unwrap();
+        assert_eq!(values.len(), 256);
+        assert_eq!(values[0], -2.0);
+        assert_eq!(values[32], -1.5);
+        assert_eq!(values[127], -0.5);
+        assert_eq!(values[128], 0.0);
+        assert_eq!(values[255], 1.5);
+    }
+
+    #[test]
+    fn dequantize_invalid_size() {
+        assert!(dequantize(TensorType::F16, &[0, 0, 0]).is_err());
+        assert!(dequantize(TensorType::Q8_0, &[0; 33]).is_err());
+        assert!(dequantize(TensorType::Count, &[]).is_err());
+    }
+}
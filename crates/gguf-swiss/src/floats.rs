@@ -0,0 +1,134 @@
+//! Bulk conversion between little-endian float data and f32 values.
+//!
+//! f16 conversions go through `half`'s slice APIs, which use hardware instructions (F16C on x86,
+//! FP16 on aarch64) when the CPU supports them. The other conversions are simple enough for the
+//! compiler to vectorize.
+
+use half::{
+    bf16, f16,
+    slice::{HalfBitsSliceExt, HalfFloatSliceExt},
+};
+
+/// Values converted at a time through a buffer on the stack.
+const BUFFER_VALUES: usize = 1024;
+
+/// Decode little-endian f16 data to f32 values.
+///
+/// Trailing bytes that don't form a full value are ignored.
+pub fn decode_f16(data: &[u8]) -> Vec<f32> {
+    let mut values = vec![0f32; data.len() / 2];
+    let mut bits = [0u16; BUFFER_VALUES];
+
+    for (data, values) in data
+        .chunks(BUFFER_VALUES * 2)
+        .zip(values.chunks_mut(BUFFER_VALUES))
+    {
+        let bits = read_bits(data, &mut bits[..values.len()]);
+        bits.reinterpret_cast::<f16>().convert_to_f32_slice(values);
+    }
+
+    values
+}
+
+/// Decode little-endian bf16 data to f32 values.
+///
+/// Trailing bytes that don't form a full value are ignored.
+pub fn decode_bf16(data: &[u8]) -> Vec<f32> {
+    // bf16 is the upper half of an f32, so this doesn't need any rounding
+    data.chunks_exact(2)
+        .map(|b| f32::from_bits((u16::from_le_bytes([b[0], b[1]]) as u32) << 16))
+        .collect()
+}
+
+/// Decode little-endian f32 data to f32 values.
+///
+/// Trailing bytes that don't form a full value are ignored.
+pub fn decode_f32(data: &[u8]) -> Vec<f32> {
+    data.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// Encode f32 values as little-endian f16 data.
+pub fn encode_f16(values: &[f32]) -> Vec<u8> {
+    let mut data = vec![0u8; values.len() * 2];
+    let mut halves = [f16::ZERO; BUFFER_VALUES];
+
+    for (values, data) in values
+        .chunks(BUFFER_VALUES)
+        .zip(data.chunks_mut(BUFFER_VALUES * 2))
+    {
+        let halves = &mut halves[..values.len()];
+        halves.convert_from_f32_slice(values);
+        write_bits(halves.reinterpret_cast(), data);
+    }
+
+    data
+}
+
+/// Encode f32 values as little-endian bf16 data, rounding to nearest even.
+pub fn encode_bf16(values: &[f32]) -> Vec<u8> {
+    let mut data = vec![0u8; values.len() * 2];
+
+    for (bytes, value) in data.chunks_exact_mut(2).zip(values) {
+        bytes.copy_from_slice(&bf16::from_f32(*value).to_le_bytes());
+    }
+
+    data
+}
+
+/// Encode f32 values as little-endian f32 data.
+pub fn encode_f32(values: &[f32]) -> Vec<u8> {
+    let mut data = vec![0u8; values.len() * 4];
+
+    for (bytes, value) in data.chunks_exact_mut(4).zip(values) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+
+    data
+}
+
+fn read_bits<'a>(data: &[u8], bits: &'a mut [u16]) -> &'a [u16] {
+    for (value, b) in bits.iter_mut().zip(data.chunks_exact(2)) {
+        *value = u16::from_le_bytes([b[0], b[1]]);
+    }
+
+    bits
+}
+
+fn write_bits(bits: &[u16], data: &mut [u8]) {
+    for (bytes, value) in data.chunks_exact_mut(2).zip(bits) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use half::{bf16, f16};
+
+    use crate::{decode_bf16, decode_f16, decode_f32, encode_bf16, encode_f16, encode_f32};
+
+    #[test]
+    fn round_trip() {
+        // More than one buffer, with a remainder that doesn't fill SIMD lanes
+        let values: Vec<f32> = (0..1037).map(|i| (i % 256) as f32 * 0.5 - 64.0).collect();
+
+        let expected: Vec<u8> = values
+            .iter()
+            .flat_map(|v| f16::from_f32(*v).to_le_bytes())
+            .collect();
+        assert_eq!(encode_f16(&values), expected);
+        assert_eq!(decode_f16(&expected), values);
+
+        let expected: Vec<u8> = values
+            .iter()
+            .flat_map(|v| bf16::from_f32(*v).to_le_bytes())
+            .collect();
+        assert_eq!(encode_bf16(&values), expected);
+        assert_eq!(decode_bf16(&expected), values);
+
+        let data = encode_f32(&values);
+        assert_eq!(data.len(), values.len() * 4);
+        assert_eq!(decode_f32(&data), values);
+    }
+}
@@ -0,0 +1,66 @@
+/// Match a value against a glob pattern, where `*` matches any sequence of characters, including
+/// dots, and `?` matches a single character.
+///
+/// Runs in `O(pattern * value)` time, as only the last `*` is ever backtracked to.
+pub fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let mut p = 0;
+    let mut v = 0;
+
+    // Position of the last `*`, and the value position it was matched up to
+    let mut backtrack = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => {
+                // Let the last `*` consume one more character
+                let Some((star, matched)) = backtrack else {
+                    return false;
+                };
+                backtrack = Some((star, matched + 1));
+                p = star + 1;
+                v = matched + 1;
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glob::glob_match;
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("general.*", "general.name"));
+        assert!(glob_match("blk.*.attn_*", "blk.12.attn_norm.weight"));
+        assert!(glob_match("blk.?.ffn", "blk.3.ffn"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match(
+            "model-*.safetensors",
+            "model-00001-of-00002.safetensors"
+        ));
+        assert!(!glob_match("blk.?.ffn", "blk.10.ffn"));
+        assert!(!glob_match("general.*", "llama.context_length"));
+        assert!(!glob_match("*.weight", "output.bias"));
+        assert!(!glob_match(
+            "model-*.safetensors",
+            "model.safetensors.index.json"
+        ));
+
+        // Would take exponential time with naive backtracking
+        let value = "a".repeat(1000);
+        assert!(!glob_match(&format!("{}b", "*a".repeat(100)), &value));
+    }
+}
@@ -6,22 +6,45 @@
 //! partial codepoints. Partial codepoints are not valid UTF-8, but are necessary for some
 //! tokenizers. For this reason strings in this library are represented as byte sequences.
 
+#[cfg(feature = "async")]
+mod async_io;
+mod convert;
+mod dequantize;
 mod dimensions;
+mod floats;
+mod glob;
 mod metadata;
 mod read;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod split;
 mod write;
 
+use anyhow::{bail, Context, Error};
+
+#[cfg(feature = "async")]
+pub use crate::async_io::{read_header_async, write_header_async};
 pub use crate::{
+    convert::convert_tensor_data,
+    dequantize::dequantize,
     dimensions::TensorDimensions,
-    metadata::{MetadataArray, MetadataType, MetadataValue},
-    read::read_header,
-    write::write_header,
+    floats::{decode_bf16, decode_f16, decode_f32, encode_bf16, encode_f16, encode_f32},
+    glob::glob_match,
+    metadata::{format_bytes, MetadataArray, MetadataType, MetadataValue},
+    read::{read_header, read_tensor_chunks, read_tensor_data, IncrementalReader, ReadProgress},
+    split::{
+        is_split_key, split_metadata, SplitModel, KEY_SPLIT_COUNT, KEY_SPLIT_NO,
+        KEY_SPLIT_TENSORS_COUNT,
+    },
+    write::{write_header, write_padding, write_tensor_data},
 };
 
 const MAGIC_NUMBER: [u8; 4] = [0x47, 0x47, 0x55, 0x46];
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::metadata_map"))]
     pub metadata: Vec<(String, MetadataValue)>,
     pub tensors: Vec<TensorInfo>,
 }
@@ -34,10 +57,74 @@ impl Header {
     pub fn find_tensor(&self, key: &str) -> Option<&TensorInfo> {
         self.tensors.iter().find(|v| v.name == key)
     }
+
+    /// Rename a tensor, keeping its position.
+    pub fn rename_tensor(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        if self.find_tensor(to).is_some() {
+            bail!("tensor {:?} already exists", to);
+        }
+
+        let tensor = self.find_tensor_mut(from)?;
+        tensor.name = to.to_string();
+
+        Ok(())
+    }
+
+    /// Remove a tensor, returning its info.
+    ///
+    /// Offsets of other tensors are left as-is, call `update_offsets` to close the gap.
+    pub fn remove_tensor(&mut self, name: &str) -> Result<TensorInfo, Error> {
+        let Some(index) = self.tensors.iter().position(|t| t.name == name) else {
+            bail!("tensor {:?} not found", name);
+        };
+
+        Ok(self.tensors.remove(index))
+    }
+
+    /// Change the type of a tensor.
+    ///
+    /// The tensor's data has to be converted to match, see `convert_tensor_data`.
+    /// Offsets are left as-is, call `update_offsets` to fit the new size.
+    pub fn retype_tensor(&mut self, name: &str, tensor_type: TensorType) -> Result<(), Error> {
+        let tensor = self.find_tensor_mut(name)?;
+
+        if tensor.dimensions.total() % tensor_type.block_size() != 0 {
+            bail!(
+                "tensor {:?} can't be divided into {:?} blocks",
+                name,
+                tensor_type
+            );
+        }
+
+        tensor.tensor_type = tensor_type;
+        Ok(())
+    }
+
+    /// Recalculate the offsets of all tensors, packing them in order with alignment.
+    ///
+    /// Returns the total size of the tensor data.
+    pub fn update_offsets(&mut self) -> u64 {
+        let mut next_offset = 0;
+
+        for tensor in &mut self.tensors {
+            tensor.offset = next_offset;
+            next_offset = align_offset(next_offset + tensor.size());
+        }
+
+        next_offset
+    }
+
+    fn find_tensor_mut(&mut self, name: &str) -> Result<&mut TensorInfo, Error> {
+        self.tensors
+            .iter_mut()
+            .find(|t| t.name == name)
+            .with_context(|| format!("tensor {:?} not found", name))
+    }
 }
 
 /// Info about a tensor inside a GGUF file.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TensorInfo {
     /// The identifying name of the tensor.
     pub name: String,
@@ -52,9 +139,18 @@ pub struct TensorInfo {
     pub offset: u64,
 }
 
+impl TensorInfo {
+    /// Size of the tensor's values in bytes.
+    pub fn size(&self) -> u64 {
+        let blocks = self.dimensions.total() / self.tensor_type.block_size();
+        blocks * self.tensor_type.type_size()
+    }
+}
+
 #[allow(non_camel_case_types)]
 #[repr(u32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TensorType {
     F32 = 0,
     F16 = 1,
@@ -102,6 +198,43 @@ impl TensorType {
 
         Some(value)
     }
+
+    /// Amount of scalars in a single block of this type.
+    ///
+    /// Non-quantized types have a block size of 1.
+    pub fn block_size(&self) -> u64 {
+        match self {
+            Self::F32 | Self::F16 | Self::I8 | Self::I16 | Self::I32 | Self::Count => 1,
+            Self::Q4_0 | Self::Q4_1 | Self::Q5_0 | Self::Q5_1 | Self::Q8_0 | Self::Q8_1 => 32,
+            Self::Q2_K | Self::Q3_K | Self::Q4_K | Self::Q5_K | Self::Q6_K | Self::Q8_K => 256,
+        }
+    }
+
+    /// Size in bytes of a single block of this type.
+    ///
+    /// `Count` is not a real type, and has a size of 0.
+    pub fn type_size(&self) -> u64 {
+        match self {
+            Self::F32 => 4,
+            Self::F16 => 2,
+            Self::Q4_0 => 18,
+            Self::Q4_1 => 20,
+            Self::Q5_0 => 22,
+            Self::Q5_1 => 24,
+            Self::Q8_0 => 34,
+            Self::Q8_1 => 36,
+            Self::Q2_K => 84,
+            Self::Q3_K => 110,
+            Self::Q4_K => 144,
+            Self::Q5_K => 176,
+            Self::Q6_K => 210,
+            Self::Q8_K => 292,
+            Self::I8 => 1,
+            Self::I16 => 2,
+            Self::I32 => 4,
+            Self::Count => 0,
+        }
+    }
 }
 
 /// Align an offset value to the next aligned value.
@@ -111,3 +244,78 @@ impl TensorType {
 pub fn align_offset(offset: u64) -> u64 {
     offset + (32 - (offset % 32)) % 32
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{Header, TensorDimensions, TensorInfo, TensorType};
+
+    fn test_header() -> Header {
+        let tensor = |name: &str, width| TensorInfo {
+            name: name.to_string(),
+            tensor_type: TensorType::F32,
+            dimensions: TensorDimensions([width, 2, 0, 0]),
+            offset: 0,
+        };
+
+        let mut header = Header {
+            metadata: Vec::new(),
+            tensors: vec![tensor("a", 3), tensor("b", 32), tensor("c", 5)],
+        };
+        header.update_offsets();
+        header
+    }
+
+    fn names(header: &Header) -> Vec<&str> {
+        header.tensors.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    #[test]
+    fn rename_tensor() {
+        let mut header = test_header();
+
+        header.rename_tensor("b", "d").unwrap();
+        assert_eq!(names(&header), ["a", "d", "c"]);
+
+        assert!(header.rename_tensor("d", "a").is_err());
+        assert!(header.rename_tensor("b", "e").is_err());
+    }
+
+    #[test]
+    fn remove_tensor() {
+        let mut header = test_header();
+
+        let removed = header.remove_tensor("b").unwrap();
+        assert_eq!(removed.offset, 32);
+        assert_eq!(names(&header), ["a", "c"]);
+        assert_eq!(header.tensors[1].offset, 288);
+
+        assert!(header.remove_tensor("b").is_err());
+    }
+
+    #[test]
+    fn retype_tensor() {
+        let mut header = test_header();
+
+        header.retype_tensor("b", TensorType::Q8_0).unwrap();
+        assert_eq!(header.tensors[1].tensor_type, TensorType::Q8_0);
+
+        // 3 * 2 values don't fit in blocks of 32
+        assert!(header.retype_tensor("a", TensorType::Q8_0).is_err());
+        assert!(header.retype_tensor("d", TensorType::F16).is_err());
+    }
+
+    #[test]
+    fn update_offsets() {
+        let mut header = test_header();
+        let offsets =
+            |header: &Header| -> Vec<u64> { header.tensors.iter().map(|t| t.offset).collect() };
+
+        // Every tensor starts aligned, after the previous one
+        assert_eq!(offsets(&header), [0, 32, 288]);
+
+        header.retype_tensor("b", TensorType::F16).unwrap();
+        header.remove_tensor("a").unwrap();
+        assert_eq!(header.update_offsets(), 192);
+        assert_eq!(offsets(&header), [0, 128]);
+    }
+}
@@ -1,5 +1,13 @@
+use std::{
+    fmt::{Display, Formatter, Write},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Error};
+
 #[repr(u32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MetadataType {
     UInt8 = 0,
     Int8 = 1,
@@ -37,9 +45,44 @@ impl MetadataType {
 
         Some(value)
     }
+
+    /// Lowercase name of the type, as used on the command line and in manifests.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UInt8 => "uint8",
+            Self::Int8 => "int8",
+            Self::UInt16 => "uint16",
+            Self::Int16 => "int16",
+            Self::UInt32 => "uint32",
+            Self::Int32 => "int32",
+            Self::Float32 => "float32",
+            Self::Bool => "bool",
+            Self::String => "string",
+            Self::Array => "array",
+            Self::UInt64 => "uint64",
+            Self::Int64 => "int64",
+            Self::Float64 => "float64",
+        }
+    }
+}
+
+/// Parses the lowercase name of a type, see `MetadataType::name`.
+impl FromStr for MetadataType {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        (0..13)
+            .filter_map(Self::from_u32)
+            .find(|ty| ty.name() == value)
+            .with_context(|| format!("unknown metadata type {:?}", value))
+    }
 }
 
+/// A metadata value.
+///
+/// Values compare floats by their bits, so a NaN value is equal to itself.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MetadataValue {
     UInt8(u8),
     Int8(i8),
@@ -47,13 +90,13 @@ pub enum MetadataValue {
     Int16(i16),
     UInt32(u32),
     Int32(i32),
-    Float32(f32),
+    Float32(#[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::float"))] f32),
     Bool(bool),
-    String(Vec<u8>),
+    String(#[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::bytes"))] Vec<u8>),
     Array(MetadataArray),
     UInt64(u64),
     Int64(i64),
-    Float64(f64),
+    Float64(#[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::float"))] f64),
 }
 
 impl MetadataValue {
@@ -74,9 +117,85 @@ impl MetadataValue {
             Self::Float64(_) => MetadataType::Float64,
         }
     }
+
+    /// Parse a value of a type from text, like `2048`, `1e-5`, or `true`.
+    ///
+    /// Strings are taken as-is, arrays are not supported.
+    pub fn parse(ty: MetadataType, value: &str) -> Result<Self, Error> {
+        let parsed = match ty {
+            MetadataType::UInt8 => value.parse().map(Self::UInt8).ok(),
+            MetadataType::Int8 => value.parse().map(Self::Int8).ok(),
+            MetadataType::UInt16 => value.parse().map(Self::UInt16).ok(),
+            MetadataType::Int16 => value.parse().map(Self::Int16).ok(),
+            MetadataType::UInt32 => value.parse().map(Self::UInt32).ok(),
+            MetadataType::Int32 => value.parse().map(Self::Int32).ok(),
+            MetadataType::Float32 => value.parse().map(Self::Float32).ok(),
+            MetadataType::Bool => value.parse().map(Self::Bool).ok(),
+            MetadataType::String => Some(Self::String(value.as_bytes().to_vec())),
+            MetadataType::Array => bail!("parsing arrays is not supported"),
+            MetadataType::UInt64 => value.parse().map(Self::UInt64).ok(),
+            MetadataType::Int64 => value.parse().map(Self::Int64).ok(),
+            MetadataType::Float64 => value.parse().map(Self::Float64).ok(),
+        };
+
+        parsed.with_context(|| format!("{:?} is not a valid {} value", value, ty.name()))
+    }
+
+    /// Name of the value's type, including the element type of arrays, like `Array<String>`.
+    pub fn type_name(&self) -> String {
+        match self {
+            Self::Array(array) => format!("Array<{:?}>", array.ty()),
+            value => format!("{:?}", value.ty()),
+        }
+    }
+}
+
+impl PartialEq for MetadataValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::UInt8(a), Self::UInt8(b)) => a == b,
+            (Self::Int8(a), Self::Int8(b)) => a == b,
+            (Self::UInt16(a), Self::UInt16(b)) => a == b,
+            (Self::Int16(a), Self::Int16(b)) => a == b,
+            (Self::UInt32(a), Self::UInt32(b)) => a == b,
+            (Self::Int32(a), Self::Int32(b)) => a == b,
+            (Self::Float32(a), Self::Float32(b)) => a.to_bits() == b.to_bits(),
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Array(a), Self::Array(b)) => a == b,
+            (Self::UInt64(a), Self::UInt64(b)) => a == b,
+            (Self::Int64(a), Self::Int64(b)) => a == b,
+            (Self::Float64(a), Self::Float64(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+/// Formats the value for humans, with strings quoted as by `format_bytes`, and arrays summarized
+/// by their type and length.
+impl Display for MetadataValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UInt8(value) => write!(f, "{}", value),
+            Self::Int8(value) => write!(f, "{}", value),
+            Self::UInt16(value) => write!(f, "{}", value),
+            Self::Int16(value) => write!(f, "{}", value),
+            Self::UInt32(value) => write!(f, "{}", value),
+            Self::Int32(value) => write!(f, "{}", value),
+            Self::Float32(value) => write!(f, "{}", value),
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{}", format_bytes(value)),
+            Self::Array(value) => write!(f, "[{:?}; {}]", value.ty(), value.len()),
+            Self::UInt64(value) => write!(f, "{}", value),
+            Self::Int64(value) => write!(f, "{}", value),
+            Self::Float64(value) => write!(f, "{}", value),
+        }
+    }
 }
 
+/// A metadata array, compared like `MetadataValue`.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MetadataArray {
     UInt8(Vec<u8>),
     Int8(Vec<i8>),
@@ -84,13 +203,15 @@ pub enum MetadataArray {
     Int16(Vec<i16>),
     UInt32(Vec<u32>),
     Int32(Vec<i32>),
-    Float32(Vec<f32>),
+    Float32(#[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::float_vec"))] Vec<f32>),
     Bool(Vec<bool>),
-    String(Vec<Vec<u8>>),
+    String(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::bytes_vec"))] Vec<Vec<u8>>,
+    ),
     Array(Vec<MetadataArray>),
     UInt64(Vec<u64>),
     Int64(Vec<i64>),
-    Float64(Vec<f64>),
+    Float64(#[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::float_vec"))] Vec<f64>),
 }
 
 impl MetadataArray {
@@ -129,4 +250,106 @@ impl MetadataArray {
             Self::Float64(v) => v.len(),
         }
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl PartialEq for MetadataArray {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::UInt8(a), Self::UInt8(b)) => a == b,
+            (Self::Int8(a), Self::Int8(b)) => a == b,
+            (Self::UInt16(a), Self::UInt16(b)) => a == b,
+            (Self::Int16(a), Self::Int16(b)) => a == b,
+            (Self::UInt32(a), Self::UInt32(b)) => a == b,
+            (Self::Int32(a), Self::Int32(b)) => a == b,
+            (Self::Float32(a), Self::Float32(b)) => bits_eq(a, b, |v| v.to_bits() as u64),
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Array(a), Self::Array(b)) => a == b,
+            (Self::UInt64(a), Self::UInt64(b)) => a == b,
+            (Self::Int64(a), Self::Int64(b)) => a == b,
+            (Self::Float64(a), Self::Float64(b)) => bits_eq(a, b, |v| v.to_bits()),
+            _ => false,
+        }
+    }
+}
+
+fn bits_eq<T>(a: &[T], b: &[T], to_bits: impl Fn(&T) -> u64) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| to_bits(a) == to_bits(b))
+}
+
+/// Format a GGUF string in quotes, escaping any bytes that aren't valid UTF-8 as `\xNN`.
+pub fn format_bytes(value: &[u8]) -> String {
+    let mut text = String::from("\"");
+
+    for chunk in value.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            text.extend(c.escape_debug());
+        }
+        for byte in chunk.invalid() {
+            write!(text, "\\x{:02x}", byte).unwrap();
+        }
+    }
+
+    text.push('"');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{format_bytes, MetadataArray, MetadataType, MetadataValue};
+
+    #[test]
+    fn format_values() {
+        assert_eq!(format_bytes(b"a\"b\n"), "\"a\\\"b\\n\"");
+        assert_eq!(format_bytes(&[b'x', 0xe4, 0xbd]), "\"x\\xe4\\xbd\"");
+
+        let array = MetadataValue::Array(MetadataArray::String(Vec::new()));
+        assert_eq!(array.to_string(), "[String; 0]");
+        assert_eq!(array.type_name(), "Array<String>");
+    }
+
+    #[test]
+    fn parse_values() {
+        let parse = |ty: &str, value| MetadataValue::parse(ty.parse().unwrap(), value);
+
+        assert_eq!(
+            parse("uint32", "2048").unwrap(),
+            MetadataValue::UInt32(2048)
+        );
+        assert_eq!(parse("int8", "-3").unwrap(), MetadataValue::Int8(-3));
+        assert_eq!(
+            parse("float32", "1e-5").unwrap(),
+            MetadataValue::Float32(1e-5)
+        );
+        assert_eq!(parse("bool", "true").unwrap(), MetadataValue::Bool(true));
+        assert_eq!(
+            parse("string", " a b ").unwrap(),
+            MetadataValue::String(b" a b ".to_vec())
+        );
+
+        assert!(parse("uint8", "256").is_err());
+        assert!(parse("uint32", "-1").is_err());
+        assert!(parse("int32", "1.5").is_err());
+        assert!(parse("bool", "1").is_err());
+        assert!(parse("array", "[]").is_err());
+        assert!("UInt8".parse::<MetadataType>().is_err());
+    }
+
+    #[test]
+    fn compare_nan_values() {
+        assert_eq!(
+            MetadataValue::Float32(f32::NAN),
+            MetadataValue::Float32(f32::NAN)
+        );
+        assert_ne!(MetadataValue::Float64(0.0), MetadataValue::Float64(-0.0));
+        assert_ne!(MetadataValue::UInt32(1), MetadataValue::UInt64(1));
+
+        let array = MetadataArray::Float64(vec![1.0, f64::NAN]);
+        assert_eq!(array, array.clone());
+        assert_ne!(array, MetadataArray::Float64(vec![1.0]));
+    }
 }
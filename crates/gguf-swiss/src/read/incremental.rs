@@ -0,0 +1,206 @@
+use std::io::{self, ErrorKind, Read};
+
+use anyhow::Error;
+
+use crate::{read::read_header, Header};
+
+/// Reader for a GGUF header that is supplied incrementally.
+///
+/// Instead of failing when the header is incomplete, this reports how many more bytes are needed
+/// at minimum, so the caller can fetch more on demand, for example using HTTP range requests.
+///
+/// The amount reported is a lower bound, the header may need more once those bytes are known.
+/// Every attempt re-parses the buffered data from the start, so fetching generously sized chunks
+/// rather than exactly the reported amount is recommended.
+#[derive(Debug, Default)]
+pub struct IncrementalReader {
+    buffer: Vec<u8>,
+    required: usize,
+}
+
+/// Progress of an `IncrementalReader`.
+#[derive(Debug)]
+pub enum ReadProgress {
+    /// At least this many more bytes are needed to read the header.
+    NeedMore(usize),
+
+    /// The header has been fully read.
+    Complete {
+        header: Header,
+
+        /// Size of the header in bytes, tensor data starts at the next aligned offset.
+        size: usize,
+    },
+}
+
+impl IncrementalReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Amount of bytes supplied so far, which is also the offset of the next byte to supply.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Append the next bytes of the file.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Try to read the header from the bytes supplied so far.
+    pub fn read(&mut self) -> Result<ReadProgress, Error> {
+        // Don't bother parsing again if we already know it can't succeed
+        if self.buffer.len() < self.required {
+            return Ok(ReadProgress::NeedMore(self.required - self.buffer.len()));
+        }
+
+        let progress = read_header_slice(&self.buffer)?;
+
+        if let ReadProgress::NeedMore(needed) = progress {
+            self.required = self.buffer.len() + needed;
+        }
+
+        Ok(progress)
+    }
+}
+
+/// Read the header from a buffer that may not contain the full header yet.
+fn read_header_slice(data: &[u8]) -> Result<ReadProgress, Error> {
+    let mut reader = SliceReader {
+        data,
+        position: 0,
+        missing: 0,
+    };
+
+    match read_header(&mut reader) {
+        Ok(header) => Ok(ReadProgress::Complete {
+            header,
+            size: reader.position,
+        }),
+        Err(_) if reader.missing != 0 => Ok(ReadProgress::NeedMore(reader.missing)),
+        Err(error) => Err(error),
+    }
+}
+
+/// Slice reader that keeps track of how many bytes were missing when it ran out.
+struct SliceReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    missing: usize,
+}
+
+impl Read for SliceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = &self.data[self.position..];
+
+        let amount = remaining.len().min(buf.len());
+        buf[..amount].copy_from_slice(&remaining[..amount]);
+        self.position += amount;
+
+        Ok(amount)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let remaining = &self.data[self.position..];
+
+        if remaining.len() < buf.len() {
+            self.missing = buf.len() - remaining.len();
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+
+        buf.copy_from_slice(&remaining[..buf.len()]);
+        self.position += buf.len();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        write_header, Header, IncrementalReader, MetadataArray, MetadataValue, ReadProgress,
+        TensorDimensions, TensorInfo, TensorType,
+    };
+
+    /// Source that only hands out data in the ranges asked for, like a remote file would.
+    struct ChunkedSource {
+        data: Vec<u8>,
+        requests: usize,
+    }
+
+    impl ChunkedSource {
+        fn fetch(&mut self, offset: usize, length: usize) -> &[u8] {
+            self.requests += 1;
+            let end = (offset + length).min(self.data.len());
+            &self.data[offset..end]
+        }
+    }
+
+    fn test_header() -> Header {
+        let tokens = (0..1000)
+            .map(|i| format!("token {}", i).into_bytes())
+            .collect();
+
+        Header {
+            metadata: vec![
+                (
+                    "general.architecture".to_string(),
+                    MetadataValue::String(b"rwkv5".to_vec()),
+                ),
+                (
+                    "tokenizer.ggml.tokens".to_string(),
+                    MetadataValue::Array(MetadataArray::String(tokens)),
+                ),
+            ],
+            tensors: vec![TensorInfo {
+                name: "token_embd.weight".to_string(),
+                tensor_type: TensorType::F32,
+                dimensions: TensorDimensions([32, 1000, 0, 0]),
+                offset: 0,
+            }],
+        }
+    }
+
+    #[test]
+    fn incremental_reader_chunked() {
+        let mut data = Vec::new();
+        write_header(&mut data, &test_header()).unwrap();
+        let header_size = data.len();
+        data.extend_from_slice(&[0u8; 4096]);
+
+        let mut source = ChunkedSource { data, requests: 0 };
+        let mut reader = IncrementalReader::new();
+
+        let (header, size) = loop {
+            match reader.read().unwrap() {
+                ReadProgress::NeedMore(needed) => {
+                    assert!(needed > 0);
+                    let bytes = source.fetch(reader.buffered(), needed.max(1024));
+                    reader.push(bytes);
+                }
+                ReadProgress::Complete { header, size } => break (header, size),
+            }
+        };
+
+        assert_eq!(size, header_size);
+        assert_eq!(header.metadata.len(), 2);
+        assert_eq!(header.tensors[0].name, "token_embd.weight");
+        assert!(source.requests > 1);
+    }
+
+    #[test]
+    fn incremental_reader_exact_need() {
+        let mut reader = IncrementalReader::new();
+
+        // Nothing supplied yet, we need at least the magic number
+        let ReadProgress::NeedMore(needed) = reader.read().unwrap() else {
+            panic!("expected incomplete header");
+        };
+        assert_eq!(needed, 4);
+
+        // Invalid data should still fail
+        reader.push(b"NOPE");
+        assert!(reader.read().is_err());
+    }
+}
@@ -0,0 +1,66 @@
+//! Protection against unreasonably large values, shared by the sync and async readers.
+
+use anyhow::{bail, Error};
+
+/// Maximum amount of tensors, and of metadata entries.
+const MAX_COUNT: u64 = 1024;
+
+const MAX_STRING_LENGTH: u64 = 65535;
+
+/// This is a very large value, but it's necessary for some vocabs.
+const MAX_ARRAY_LENGTH: u64 = 524288;
+
+/// Maximum nesting depth of arrays, counting from 0 for a top-level array.
+const MAX_ARRAY_DEPTH: usize = 2;
+
+const MAX_DIMENSIONS: u32 = 4;
+
+/// Currently, only 2 and 3 are supported. These versions are mostly identical except for that
+/// version 3 can hypothetically contain big-endian values (but has no mechanism for indicating
+/// this, so for now we just ignore this).
+/// Version 1 has 32-bit counts, and will require more work to support if desired.
+pub fn check_version(version: u32) -> Result<(), Error> {
+    if version != 3 && version != 2 {
+        bail!("unsupported gguf version: {}", version);
+    }
+
+    Ok(())
+}
+
+pub fn check_counts(tensor_count: u64, metadata_kv_count: u64) -> Result<(), Error> {
+    if tensor_count > MAX_COUNT {
+        bail!("excessive tensor count");
+    }
+    if metadata_kv_count > MAX_COUNT {
+        bail!("excessive metadata count");
+    }
+
+    Ok(())
+}
+
+pub fn check_string_length(length: u64) -> Result<(), Error> {
+    if length > MAX_STRING_LENGTH {
+        bail!("invalid string: too long");
+    }
+
+    Ok(())
+}
+
+pub fn check_array(depth: usize, length: u64) -> Result<(), Error> {
+    if depth > MAX_ARRAY_DEPTH {
+        bail!("excessive metadata depth");
+    }
+    if length > MAX_ARRAY_LENGTH {
+        bail!("excessive array size");
+    }
+
+    Ok(())
+}
+
+pub fn check_dimensions_count(count: u32) -> Result<(), Error> {
+    if count > MAX_DIMENSIONS {
+        bail!("invalid tensor: too many dimensions")
+    }
+
+    Ok(())
+}
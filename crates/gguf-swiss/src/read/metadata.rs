@@ -1,12 +1,15 @@
 use std::io::Read;
 
-use anyhow::{bail, Context, Error};
+use anyhow::{Context, Error};
 
 use crate::read::primitives::read_bool;
 use crate::{
-    read::primitives::{
-        read_f32, read_f64, read_i16, read_i32, read_i64, read_i8, read_string, read_u16, read_u32,
-        read_u64, read_u8,
+    read::{
+        limits,
+        primitives::{
+            read_f32, read_f64, read_i16, read_i32, read_i64, read_i8, read_string, read_u16,
+            read_u32, read_u64, read_u8,
+        },
     },
     MetadataArray, MetadataType, MetadataValue,
 };
@@ -38,19 +41,11 @@ pub fn read_metadata_entry(reader: &mut impl Read) -> Result<(String, MetadataVa
 }
 
 fn read_array(reader: &mut impl Read, depth: usize) -> Result<MetadataArray, Error> {
-    if depth > 2 {
-        bail!("excessive metadata depth");
-    }
-
     let type_index = read_u32(reader)?;
     let ty = MetadataType::from_u32(type_index).context("invalid type")?;
 
     let length = read_u64(reader)?;
-
-    // This is a very large value, but it's necessary for some vocabs
-    if length > 524288 {
-        bail!("excessive array size");
-    }
+    limits::check_array(depth, length)?;
 
     let value = match ty {
         MetadataType::UInt8 => MetadataArray::UInt8(array_inner(length, reader, read_u8)?),
@@ -1,10 +1,14 @@
+mod incremental;
+pub(crate) mod limits;
 mod metadata;
 mod primitives;
 
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 
 use anyhow::{bail, Context, Error};
 
+pub use crate::read::incremental::{IncrementalReader, ReadProgress};
+
 use crate::{
     read::{
         metadata::read_metadata_entry,
@@ -25,26 +29,15 @@ pub fn read_header(reader: &mut impl Read) -> Result<Header, Error> {
     }
 
     // Validate we're reading a supported version
-    // Currently, only 2 and 3 are supported. These versions are mostly identical except for that
-    // version 3 can hypothetically contain big-endian values (but has no mechanism for indicating
-    // this, so for now we just ignore this).
-    // Version 1 has 32-bit counts, and will require more work to support if desired.
     let gguf_version = read_u32(reader)?;
-    if gguf_version != 3 && gguf_version != 2 {
-        bail!("unsupported gguf version: {}", gguf_version);
-    }
+    limits::check_version(gguf_version)?;
 
     // Read header data
     let tensor_count = read_u64(reader)?;
     let metadata_kv_count = read_u64(reader)?;
 
     // Protection against unreasonably large values
-    if tensor_count > 1024 {
-        bail!("excessive tensor count");
-    }
-    if metadata_kv_count > 1024 {
-        bail!("excessive metadata count");
-    }
+    limits::check_counts(tensor_count, metadata_kv_count)?;
 
     // Read metadata KVs
     let mut metadata = Vec::new();
@@ -64,19 +57,72 @@ pub fn read_header(reader: &mut impl Read) -> Result<Header, Error> {
     Ok(value)
 }
 
+/// Read the raw data of a tensor.
+///
+/// `data_start` is the absolute position of the tensor data, the aligned offset after the header.
+pub fn read_tensor_data<R>(
+    reader: &mut R,
+    data_start: u64,
+    tensor: &TensorInfo,
+) -> Result<Vec<u8>, Error>
+where
+    R: Read + Seek,
+{
+    reader.seek(SeekFrom::Start(data_start + tensor.offset))?;
+
+    let mut data = vec![0u8; tensor.size() as usize];
+    reader
+        .read_exact(&mut data)
+        .with_context(|| format!("failed to read tensor {:?}", tensor.name))?;
+
+    Ok(data)
+}
+
+/// Read the raw data of a tensor in chunks, calling `f` with every chunk.
+///
+/// Chunks contain whole blocks, and are at most `chunk_size` bytes, but at least a single block.
+pub fn read_tensor_chunks<R, F>(
+    reader: &mut R,
+    data_start: u64,
+    tensor: &TensorInfo,
+    chunk_size: usize,
+    mut f: F,
+) -> Result<(), Error>
+where
+    R: Read + Seek,
+    F: FnMut(&[u8]) -> Result<(), Error>,
+{
+    reader.seek(SeekFrom::Start(data_start + tensor.offset))?;
+
+    let type_size = (tensor.tensor_type.type_size() as usize).max(1);
+    let chunk_size = (chunk_size / type_size).max(1) * type_size;
+
+    let mut remaining = tensor.size() as usize;
+    let mut buffer = vec![0u8; chunk_size.min(remaining)];
+
+    while remaining != 0 {
+        let chunk = &mut buffer[..chunk_size.min(remaining)];
+        reader
+            .read_exact(chunk)
+            .with_context(|| format!("failed to read tensor {:?}", tensor.name))?;
+
+        f(chunk)?;
+        remaining -= chunk.len();
+    }
+
+    Ok(())
+}
+
 fn read_tensor_info(reader: &mut impl Read) -> Result<TensorInfo, Error> {
     let name = read_string(reader)?;
     let name = String::from_utf8(name).context("name not valid utf-8")?;
 
     // Read the tensor dimensions
-    let dimensions_count = read_u32(reader)? as usize;
-
-    if dimensions_count > 4 {
-        bail!("invalid tensor: too many dimensions")
-    }
+    let dimensions_count = read_u32(reader)?;
+    limits::check_dimensions_count(dimensions_count)?;
 
     let mut dimensions = TensorDimensions([0, 0, 0, 0]);
-    for i in 0..dimensions_count {
+    for i in 0..dimensions_count as usize {
         dimensions.0[i] = read_u64(reader)?;
     }
 
@@ -1,6 +1,8 @@
 use std::io::Read;
 
-use anyhow::{bail, Error};
+use anyhow::Error;
+
+use crate::read::limits::check_string_length;
 
 pub fn read_u8(reader: &mut impl Read) -> Result<u8, Error> {
     let mut bytes = [0u8; 1];
@@ -95,13 +97,10 @@ pub fn read_bool(reader: &mut impl Read) -> Result<bool, Error> {
 }
 
 pub fn read_string(reader: &mut impl Read) -> Result<Vec<u8>, Error> {
-    let length = read_u64(reader)? as usize;
-
-    if length > 65535 {
-        bail!("invalid string: too long");
-    }
+    let length = read_u64(reader)?;
+    check_string_length(length)?;
 
-    let mut bytes = vec![0u8; length];
+    let mut bytes = vec![0u8; length as usize];
     reader.read_exact(&mut bytes)?;
 
     Ok(bytes)
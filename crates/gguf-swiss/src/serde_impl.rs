@@ -0,0 +1,475 @@
+//! Serde support, enabled with the `serde` feature.
+//!
+//! The encoding is intended to be faithful, so a header can be serialized and deserialized again
+//! without any change.
+//! Metadata values keep their exact GGUF type, as the enum variant name.
+//! Strings that are valid UTF-8 are encoded as plain strings, while strings that are not (see the
+//! crate documentation) are encoded as `{ "base64": "..." }`.
+//! Non-finite floats are encoded as the strings `"NaN"`, `"inf"` and `"-inf"`, as JSON has no
+//! representation for them. NaNs other than the canonical one keep their bits, as
+//! `"NaN(0x7fc00001)"`.
+//! Header metadata is encoded as a map, in the order the entries appear in the file.
+//! Tensor dimensions are encoded as a list of only the used dimensions, width-first.
+
+use std::{fmt::Formatter, marker::PhantomData};
+
+use serde::{
+    de::{Error as _, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::TensorDimensions;
+
+impl Serialize for TensorDimensions {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(&self.0[..self.count()])
+    }
+}
+
+impl<'de> Deserialize<'de> for TensorDimensions {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<u64>::deserialize(deserializer)?;
+
+        if values.len() > 4 {
+            return Err(D::Error::custom("too many tensor dimensions"));
+        }
+        if values.contains(&0) {
+            return Err(D::Error::custom("tensor dimensions cannot be zero"));
+        }
+
+        let mut dimensions = TensorDimensions::default();
+        dimensions.0[..values.len()].copy_from_slice(&values);
+
+        Ok(dimensions)
+    }
+}
+
+/// Encoding of GGUF strings, which may not be valid UTF-8.
+pub mod bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(value) {
+            Ok(value) => serializer.serialize_str(value),
+            Err(_) => {
+                let encoded = STANDARD.encode(value);
+                serializer.collect_map([("base64", encoded)])
+            }
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_any(BytesVisitor)
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+            write!(f, "a string, or a map with a \"base64\" key")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            Ok(value.as_bytes().to_vec())
+        }
+
+        fn visit_string<E: serde::de::Error>(self, value: String) -> Result<Self::Value, E> {
+            Ok(value.into_bytes())
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+            Ok(value.to_vec())
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let Some((key, value)) = map.next_entry::<String, String>()? else {
+                return Err(A::Error::custom("empty string map"));
+            };
+
+            if key != "base64" || map.next_key::<String>()?.is_some() {
+                return Err(A::Error::custom("string map must only contain \"base64\""));
+            }
+
+            STANDARD.decode(value).map_err(A::Error::custom)
+        }
+    }
+}
+
+/// Encoding of GGUF string arrays, see `bytes`.
+pub mod bytes_vec {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct EntryRef<'a>(#[serde(with = "super::bytes")] &'a [u8]);
+
+    #[derive(Deserialize)]
+    struct Entry(#[serde(with = "super::bytes")] Vec<u8>);
+
+    pub fn serialize<S: Serializer>(value: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(value.iter().map(|v| EntryRef(v)))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Vec<u8>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let values = Vec::<Entry>::deserialize(deserializer)?;
+        Ok(values.into_iter().map(|v| v.0).collect())
+    }
+}
+
+/// Encoding of metadata floats, which may not be finite.
+pub mod float {
+    use super::*;
+
+    pub trait Float: Copy {
+        fn to_f64(self) -> f64;
+
+        fn from_f64(value: f64) -> Self;
+
+        /// The bits of a NaN that differs from the canonical one.
+        fn nan_bits(self) -> Option<u64>;
+
+        /// The NaN with the given bits, if they fit and are a NaN.
+        fn from_nan_bits(bits: u64) -> Option<Self>;
+
+        fn serialize_finite<S: Serializer>(self, serializer: S) -> Result<S::Ok, S::Error>;
+    }
+
+    impl Float for f32 {
+        fn to_f64(self) -> f64 {
+            self as f64
+        }
+
+        fn from_f64(value: f64) -> Self {
+            value as f32
+        }
+
+        fn nan_bits(self) -> Option<u64> {
+            let bits = self.to_bits();
+            (self.is_nan() && bits != f32::NAN.to_bits()).then_some(bits as u64)
+        }
+
+        fn from_nan_bits(bits: u64) -> Option<Self> {
+            let value = f32::from_bits(u32::try_from(bits).ok()?);
+            value.is_nan().then_some(value)
+        }
+
+        fn serialize_finite<S: Serializer>(self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_f32(self)
+        }
+    }
+
+    impl Float for f64 {
+        fn to_f64(self) -> f64 {
+            self
+        }
+
+        fn from_f64(value: f64) -> Self {
+            value
+        }
+
+        fn nan_bits(self) -> Option<u64> {
+            let bits = self.to_bits();
+            (self.is_nan() && bits != f64::NAN.to_bits()).then_some(bits)
+        }
+
+        fn from_nan_bits(bits: u64) -> Option<Self> {
+            let value = f64::from_bits(bits);
+            value.is_nan().then_some(value)
+        }
+
+        fn serialize_finite<S: Serializer>(self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_f64(self)
+        }
+    }
+
+    pub fn serialize<T: Float, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        let float = value.to_f64();
+
+        if let Some(bits) = value.nan_bits() {
+            serializer.serialize_str(&format!("NaN({:#x})", bits))
+        } else if float.is_nan() {
+            serializer.serialize_str("NaN")
+        } else if float == f64::INFINITY {
+            serializer.serialize_str("inf")
+        } else if float == f64::NEG_INFINITY {
+            serializer.serialize_str("-inf")
+        } else {
+            value.serialize_finite(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, T: Float, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        deserializer.deserialize_any(FloatVisitor(PhantomData))
+    }
+
+    struct FloatVisitor<T>(PhantomData<T>);
+
+    impl<T: Float> Visitor<'_> for FloatVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+            write!(
+                f,
+                "a number, or one of \"NaN\", \"NaN(0x...)\", \"inf\" and \"-inf\""
+            )
+        }
+
+        fn visit_f64<E: serde::de::Error>(self, value: f64) -> Result<Self::Value, E> {
+            Ok(T::from_f64(value))
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Self::Value, E> {
+            Ok(T::from_f64(value as f64))
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Self::Value, E> {
+            Ok(T::from_f64(value as f64))
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            let invalid = || E::custom(format!("invalid float {:?}", value));
+
+            if let Some(bits) = value
+                .strip_prefix("NaN(0x")
+                .and_then(|v| v.strip_suffix(')'))
+            {
+                let bits = u64::from_str_radix(bits, 16).map_err(|_| invalid())?;
+                return T::from_nan_bits(bits).ok_or_else(invalid);
+            }
+
+            let value = match value {
+                "NaN" => f64::NAN,
+                "inf" => f64::INFINITY,
+                "-inf" => f64::NEG_INFINITY,
+                _ => return Err(invalid()),
+            };
+
+            Ok(T::from_f64(value))
+        }
+    }
+}
+
+/// Encoding of metadata float arrays, see `float`.
+pub mod float_vec {
+    use super::{float::Float, *};
+
+    struct Entry<T>(T);
+
+    impl<T: Float> Serialize for Entry<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            super::float::serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'de, T: Float> Deserialize<'de> for Entry<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            super::float::deserialize(deserializer).map(Entry)
+        }
+    }
+
+    pub fn serialize<T: Float, S: Serializer>(
+        value: &[T],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(value.iter().map(|v| Entry(*v)))
+    }
+
+    pub fn deserialize<'de, T: Float, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let values = Vec::<Entry<T>>::deserialize(deserializer)?;
+        Ok(values.into_iter().map(|v| v.0).collect())
+    }
+}
+
+/// Encoding of header metadata as an ordered map.
+pub mod metadata_map {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::MetadataValue;
+
+    pub fn serialize<S>(value: &[(String, MetadataValue)], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(value.iter().map(|(k, v)| (k, v)))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<(String, MetadataValue)>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(MetadataVisitor)
+    }
+
+    struct MetadataVisitor;
+
+    impl<'de> Visitor<'de> for MetadataVisitor {
+        type Value = Vec<(String, MetadataValue)>;
+
+        fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+            write!(f, "a map of metadata values")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut values = Vec::new();
+            let mut keys = HashSet::new();
+
+            while let Some((key, value)) = map.next_entry::<String, MetadataValue>()? {
+                if !keys.insert(key.clone()) {
+                    return Err(A::Error::custom(format!(
+                        "duplicate metadata key {:?}",
+                        key
+                    )));
+                }
+                values.push((key, value));
+            }
+
+            Ok(values)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut values = Vec::new();
+            let mut keys = HashSet::new();
+
+            while let Some((key, value)) = seq.next_element::<(String, MetadataValue)>()? {
+                if !keys.insert(key.clone()) {
+                    return Err(A::Error::custom(format!(
+                        "duplicate metadata key {:?}",
+                        key
+                    )));
+                }
+                values.push((key, value));
+            }
+
+            Ok(values)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Header, MetadataArray, MetadataValue, TensorDimensions, TensorInfo, TensorType};
+
+    #[test]
+    fn serde_json_roundtrip() {
+        // Partial codepoint, not valid UTF-8 on its own
+        let partial = vec![0xe4, 0xbd];
+
+        let header = Header {
+            metadata: vec![
+                ("b".to_string(), MetadataValue::UInt16(7)),
+                ("a".to_string(), MetadataValue::Float32(0.1)),
+                ("name".to_string(), MetadataValue::String(b"x".to_vec())),
+                (
+                    "tokens".to_string(),
+                    MetadataValue::Array(MetadataArray::String(vec![b"a".to_vec(), partial])),
+                ),
+            ],
+            tensors: vec![TensorInfo {
+                name: "output.weight".to_string(),
+                tensor_type: TensorType::Q8_0,
+                dimensions: TensorDimensions([64, 32, 0, 0]),
+                offset: 96,
+            }],
+        };
+
+        let json = serde_json::to_string(&header).unwrap();
+        assert!(json.contains(r#"{"b":{"UInt16":7},"a":{"Float32":0.1}"#));
+        assert!(json.contains(r#"{"base64":"5L0="}"#));
+        assert!(json.contains(r#""dimensions":[64,32]"#));
+
+        let result: Header = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&result).unwrap(), json);
+        assert_eq!(result.metadata[0].0, "b");
+        assert!(matches!(result.metadata[1].1, MetadataValue::Float32(v) if v == 0.1));
+    }
+
+    #[test]
+    fn serde_json_non_finite() {
+        let metadata = vec![
+            ("nan".to_string(), MetadataValue::Float32(f32::NAN)),
+            ("inf".to_string(), MetadataValue::Float64(f64::INFINITY)),
+            (
+                "values".to_string(),
+                MetadataValue::Array(MetadataArray::Float32(vec![1.5, f32::NEG_INFINITY])),
+            ),
+        ];
+        let header = Header {
+            metadata,
+            tensors: Vec::new(),
+        };
+
+        let json = serde_json::to_string(&header).unwrap();
+        assert!(json.contains(r#""nan":{"Float32":"NaN"}"#));
+        assert!(json.contains(r#""inf":{"Float64":"inf"}"#));
+        assert!(json.contains(r#""values":{"Array":{"Float32":[1.5,"-inf"]}}"#));
+
+        let result: Header = serde_json::from_str(&json).unwrap();
+        assert!(matches!(result.metadata[0].1, MetadataValue::Float32(v) if v.is_nan()));
+        assert_eq!(result.metadata[1..], header.metadata[1..]);
+
+        let invalid = r#"{"metadata":{"a":{"Float32":"nope"}},"tensors":[]}"#;
+        assert!(serde_json::from_str::<Header>(invalid).is_err());
+    }
+
+    #[test]
+    fn serde_json_nan_payloads() {
+        let payload = f32::from_bits(0x7fc00001);
+        let negative = -f64::NAN;
+        let metadata = vec![
+            ("a".to_string(), MetadataValue::Float32(payload)),
+            ("b".to_string(), MetadataValue::Float64(negative)),
+            (
+                "c".to_string(),
+                MetadataValue::Array(MetadataArray::Float32(vec![f32::NAN, payload])),
+            ),
+        ];
+        let header = Header {
+            metadata,
+            tensors: Vec::new(),
+        };
+
+        let json = serde_json::to_string(&header).unwrap();
+        assert!(json.contains(r#""a":{"Float32":"NaN(0x7fc00001)"}"#));
+        assert!(json.contains(r#""b":{"Float64":"NaN(0xfff8000000000000)"}"#));
+        assert!(json.contains(r#""c":{"Array":{"Float32":["NaN","NaN(0x7fc00001)"]}}"#));
+
+        let result: Header = serde_json::from_str(&json).unwrap();
+        assert!(
+            matches!(result.metadata[0].1, MetadataValue::Float32(v) if v.to_bits() == 0x7fc00001)
+        );
+        assert!(
+            matches!(result.metadata[1].1, MetadataValue::Float64(v) if v.to_bits() == negative.to_bits())
+        );
+
+        for invalid in ["NaN(0x1)", "NaN(0x1ffc00000)", "NaN(0xzz)"] {
+            let json = format!(
+                r#"{{"metadata":{{"a":{{"Float32":"{}"}}}},"tensors":[]}}"#,
+                invalid
+            );
+            assert!(
+                serde_json::from_str::<Header>(&json).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn serde_json_duplicate_keys() {
+        let json = r#"{"metadata":{"a":{"UInt8":1},"a":{"UInt8":2}},"tensors":[]}"#;
+        let error = serde_json::from_str::<Header>(json).unwrap_err();
+        assert!(error.to_string().contains("duplicate metadata key \"a\""));
+    }
+}
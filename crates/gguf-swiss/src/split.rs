@@ -0,0 +1,224 @@
+use std::io::{Read, Seek};
+
+use anyhow::{bail, Context, Error};
+
+use crate::{align_offset, read_header, read_tensor_data, Header, MetadataValue};
+
+/// Index of the shard in a split model, starting at 0.
+pub const KEY_SPLIT_NO: &str = "split.no";
+
+/// Amount of shards in a split model.
+pub const KEY_SPLIT_COUNT: &str = "split.count";
+
+/// Amount of tensors in all shards of a split model.
+pub const KEY_SPLIT_TENSORS_COUNT: &str = "split.tensors.count";
+
+/// A model split over multiple GGUF files, as created by llama.cpp's `gguf-split`, read as one
+/// logical model.
+pub struct SplitModel<R> {
+    /// Metadata of the first shard, without split keys, and the tensors of all shards.
+    ///
+    /// Tensor offsets are relative to the data of the shard the tensor is in.
+    pub header: Header,
+
+    shards: Vec<Shard<R>>,
+
+    /// Index of the shard every tensor is in.
+    tensor_shards: Vec<usize>,
+}
+
+struct Shard<R> {
+    reader: R,
+    data_start: u64,
+}
+
+impl<R: Read + Seek> SplitModel<R> {
+    /// Read the headers of all shards, in order.
+    ///
+    /// A single model that isn't split is also accepted.
+    pub fn open(readers: Vec<R>) -> Result<Self, Error> {
+        let mut header = Header::default();
+        let mut shards = Vec::new();
+        let mut tensor_shards = Vec::new();
+        let shard_count = readers.len();
+        let mut tensors_count = None;
+
+        for (index, mut reader) in readers.into_iter().enumerate() {
+            let shard_header = read_header(&mut reader)
+                .with_context(|| format!("failed to read header of shard {}", index))?;
+            let data_start = align_offset(reader.stream_position()?);
+
+            let split_no = get_u16(&shard_header, KEY_SPLIT_NO)?;
+            let split_count = get_u16(&shard_header, KEY_SPLIT_COUNT)?;
+            match (split_no, split_count) {
+                (Some(no), Some(count)) => {
+                    if no as usize != index || count as usize != shard_count {
+                        bail!(
+                            "expected shard {} of {}, found shard {} of {}",
+                            index,
+                            shard_count,
+                            no,
+                            count
+                        );
+                    }
+                }
+                (None, None) if shard_count == 1 => {}
+                _ => bail!("shard {} is missing split metadata", index),
+            }
+
+            if index == 0 {
+                tensors_count = get_i32(&shard_header, KEY_SPLIT_TENSORS_COUNT)?;
+                header.metadata = shard_header.metadata;
+                header.metadata.retain(|(key, _)| !is_split_key(key));
+            }
+            tensor_shards.extend(shard_header.tensors.iter().map(|_| index));
+            header.tensors.extend(shard_header.tensors);
+
+            shards.push(Shard { reader, data_start });
+        }
+
+        if let Some(count) = tensors_count {
+            if usize::try_from(count).ok() != Some(header.tensors.len()) {
+                bail!(
+                    "expected {} tensors in all shards, found {}",
+                    count,
+                    header.tensors.len()
+                );
+            }
+        }
+
+        Ok(Self {
+            header,
+            shards,
+            tensor_shards,
+        })
+    }
+
+    /// Amount of shards the model is split over.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Read the data of the tensor at an index of the header's tensors.
+    pub fn read_tensor_data(&mut self, index: usize) -> Result<Vec<u8>, Error> {
+        let tensor = &self.header.tensors[index];
+        let shard = &mut self.shards[self.tensor_shards[index]];
+
+        read_tensor_data(&mut shard.reader, shard.data_start, tensor)
+    }
+}
+
+/// Check if a metadata key is one of the split keys.
+pub fn is_split_key(key: &str) -> bool {
+    matches!(
+        key,
+        KEY_SPLIT_NO | KEY_SPLIT_COUNT | KEY_SPLIT_TENSORS_COUNT
+    )
+}
+
+/// Create the split metadata for a shard.
+pub fn split_metadata(no: u16, count: u16, tensors_count: i32) -> Vec<(String, MetadataValue)> {
+    vec![
+        (KEY_SPLIT_NO.to_string(), MetadataValue::UInt16(no)),
+        (KEY_SPLIT_COUNT.to_string(), MetadataValue::UInt16(count)),
+        (
+            KEY_SPLIT_TENSORS_COUNT.to_string(),
+            MetadataValue::Int32(tensors_count),
+        ),
+    ]
+}
+
+fn get_i32(header: &Header, key: &str) -> Result<Option<i32>, Error> {
+    match header.find_metadata(key) {
+        None => Ok(None),
+        Some(MetadataValue::Int32(value)) => Ok(Some(*value)),
+        Some(_) => bail!("{:?} is not an Int32", key),
+    }
+}
+
+fn get_u16(header: &Header, key: &str) -> Result<Option<u16>, Error> {
+    match header.find_metadata(key) {
+        None => Ok(None),
+        Some(MetadataValue::UInt16(value)) => Ok(Some(*value)),
+        Some(_) => bail!("{:?} is not a UInt16", key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        split_metadata, write_header, Header, MetadataValue, SplitModel, TensorDimensions,
+        TensorInfo, TensorType,
+    };
+
+    fn shard(no: u16, tensor: &str, value: u8) -> Cursor<Vec<u8>> {
+        let mut header = Header {
+            metadata: split_metadata(no, 2, 2),
+            tensors: vec![TensorInfo {
+                name: tensor.to_string(),
+                tensor_type: TensorType::I8,
+                dimensions: TensorDimensions::from_width_last(&[1]).unwrap(),
+                offset: 0,
+            }],
+        };
+        if no == 0 {
+            let value = MetadataValue::String(b"test".to_vec());
+            header.metadata.push(("general.name".to_string(), value));
+        }
+
+        let mut data = Vec::new();
+        write_header(&mut data, &header).unwrap();
+        data.resize(crate::align_offset(data.len() as u64) as usize, 0);
+        data.push(value);
+
+        Cursor::new(data)
+    }
+
+    #[test]
+    fn read_split_model() {
+        let shards = vec![shard(0, "a", 1), shard(1, "b", 2)];
+        let mut model = SplitModel::open(shards).unwrap();
+
+        assert_eq!(model.header.metadata.len(), 1);
+        assert_eq!(model.header.tensors.len(), 2);
+        assert_eq!(model.read_tensor_data(1).unwrap(), vec![2]);
+
+        let shards = vec![shard(1, "b", 2), shard(0, "a", 1)];
+        assert!(SplitModel::open(shards).is_err());
+    }
+
+    #[test]
+    fn tensors_count() {
+        // The first shard, with a different tensors count
+        let first = |value: MetadataValue| {
+            let data = shard(0, "a", 1).into_inner();
+            let mut header = crate::read_header(&mut data.as_slice()).unwrap();
+            header.metadata[2].1 = value;
+
+            let mut data = Vec::new();
+            write_header(&mut data, &header).unwrap();
+            data.resize(crate::align_offset(data.len() as u64) as usize, 0);
+            data.push(1);
+            Cursor::new(data)
+        };
+        let error = |value| {
+            let shards = vec![first(value), shard(1, "b", 2)];
+            SplitModel::open(shards).err().unwrap().to_string()
+        };
+
+        assert_eq!(
+            error(MetadataValue::Int32(3)),
+            "expected 3 tensors in all shards, found 2"
+        );
+        assert_eq!(
+            error(MetadataValue::Int32(-1)),
+            "expected -1 tensors in all shards, found 2"
+        );
+        assert_eq!(
+            error(MetadataValue::UInt32(2)),
+            "\"split.tensors.count\" is not an Int32"
+        );
+    }
+}
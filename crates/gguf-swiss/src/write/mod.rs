@@ -1,11 +1,12 @@
 mod metadata;
 mod primitives;
 
-use std::io::Write;
+use std::io::{Seek, Write};
 
-use anyhow::Error;
+use anyhow::{bail, Error};
 
 use crate::{
+    align_offset,
     write::{
         metadata::write_metadata_entry,
         primitives::{write_string, write_u32, write_u64},
@@ -33,6 +34,56 @@ pub fn write_header(writer: &mut impl Write, header: &Header) -> Result<(), Erro
     Ok(())
 }
 
+/// Write zero padding up to the next aligned position, returning that position.
+pub fn write_padding<W: Write + Seek>(writer: &mut W) -> Result<u64, Error> {
+    let current = writer.stream_position()?;
+
+    let padded = align_offset(current);
+    let padding = padded - current;
+
+    if padding != 0 {
+        let padding = vec![0u8; padding as usize];
+        writer.write_all(&padding)?;
+    }
+
+    Ok(padded)
+}
+
+/// Pad up to the data of `tensor` and write `data` there.
+///
+/// `data` may be only the start of the tensor data, with the rest written directly after.
+/// Fails if the padded position doesn't match the tensor offset, as tensors have to be written in
+/// order.
+pub fn write_tensor_data<W: Write + Seek>(
+    writer: &mut W,
+    data_start: u64,
+    tensor: &TensorInfo,
+    data: &[u8],
+) -> Result<(), Error> {
+    if data.len() as u64 > tensor.size() {
+        bail!(
+            "{} bytes of data for tensor {:?}, expected {}",
+            data.len(),
+            tensor.name,
+            tensor.size()
+        );
+    }
+
+    let position = write_padding(writer)?;
+    if position != data_start + tensor.offset {
+        bail!(
+            "tensor {:?} at position {}, expected {}",
+            tensor.name,
+            position,
+            data_start + tensor.offset
+        );
+    }
+
+    writer.write_all(data)?;
+
+    Ok(())
+}
+
 fn write_tensor_info(writer: &mut impl Write, value: &TensorInfo) -> Result<(), Error> {
     write_string(writer, value.name.as_bytes())?;
 
@@ -46,3 +97,33 @@ fn write_tensor_info(writer: &mut impl Write, value: &TensorInfo) -> Result<(),
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{write_padding, write_tensor_data, TensorDimensions, TensorInfo, TensorType};
+
+    #[test]
+    fn write_tensors_padded() {
+        let tensor = |name: &str, offset| TensorInfo {
+            name: name.to_string(),
+            tensor_type: TensorType::F32,
+            dimensions: TensorDimensions([3, 0, 0, 0]),
+            offset,
+        };
+        let mut writer = Cursor::new(vec![1u8; 5]);
+        writer.set_position(5);
+
+        let data_start = write_padding(&mut writer).unwrap();
+        assert_eq!(data_start, 32);
+        write_tensor_data(&mut writer, data_start, &tensor("a", 0), &[2; 12]).unwrap();
+        write_tensor_data(&mut writer, data_start, &tensor("b", 32), &[3; 12]).unwrap();
+        assert_eq!(writer.get_ref().len(), 76);
+        assert_eq!(writer.get_ref()[44..64], [0; 20]);
+
+        // Out of order, or too much data
+        assert!(write_tensor_data(&mut writer, data_start, &tensor("c", 32), &[]).is_err());
+        assert!(write_tensor_data(&mut writer, data_start, &tensor("d", 64), &[0; 16]).is_err());
+    }
+}